@@ -0,0 +1,68 @@
+use log::*;
+use rodio::{buffer::SamplesBuffer, Sink};
+
+use std::path::Path;
+use std::time::Duration;
+
+/// Sample rate of the audio produced by the emulators, all audio is mono.
+pub const SAMPLE_RATE: u32 = 44100;
+
+// Amount of buffers which may wait for playback before new samples are dropped
+const MAX_QUEUED: usize = 4;
+
+/// Amount of samples which fit in `elapsed`, along with the time which does not fill a sample.
+pub fn sample_count(elapsed: Duration) -> (usize, Duration) {
+    let count = elapsed.as_nanos() * SAMPLE_RATE as u128 / 1_000_000_000;
+    let used = count * 1_000_000_000 / SAMPLE_RATE as u128;
+    (count as usize, elapsed - Duration::from_nanos(used as u64))
+}
+
+/// Plays the samples produced by an emulator on the default output device.
+pub struct Player {
+    sink: Option<Sink>,
+}
+
+impl Player {
+    pub fn new() -> Player {
+        let sink = rodio::default_output_device().map(|device| Sink::new(&device));
+        if sink.is_none() {
+            warn!("No audio output device found, running without sound");
+        }
+        Player { sink }
+    }
+
+    /// Queue samples for playback.
+    ///
+    /// When the emulator produces samples faster than they are played (e.g. when the emulation
+    /// speed is increased), samples are dropped instead of letting the delay grow.
+    pub fn play(&self, samples: Vec<i16>) {
+        match &self.sink {
+            Some(sink) if !samples.is_empty() && sink.len() < MAX_QUEUED => {
+                sink.append(SamplesBuffer::new(1, SAMPLE_RATE, samples))
+            }
+            _ => (),
+        }
+    }
+}
+
+impl Default for Player {
+    fn default() -> Player {
+        Player::new()
+    }
+}
+
+/// Write samples to a 16 bit WAV file.
+pub fn write_wav<P: AsRef<Path>>(path: P, samples: &[i16]) -> Result<(), hound::Error> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    let mut writer = hound::WavWriter::create(path, spec)?;
+    for sample in samples {
+        writer.write_sample(*sample)?;
+    }
+    writer.finalize()
+}
@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+// Roms are loaded at this address
+const START: u16 = 0x200;
+
+/// Assemble a program written in the classic syntax of `disasm` into a rom.
+///
+/// Every line contains an optional label (`name:`), followed by an instruction, a directive or
+/// nothing at all. Comments start with `;`. Mnemonics and registers are case insensitive,
+/// operands are separated by commas or whitespace.
+///
+/// - Instructions use the mnemonics of Cowgod's technical reference (`LD v0, 0x12`,
+///   `DRW v0, v1, 5`, `LD [I], v3`,...) along with the Super Chip8 and XO-Chip extensions.
+/// - `name EQU value` defines a constant.
+/// - `DB` and `DW` insert bytes and (big endian) words.
+///
+/// Values are written in decimal, hexadecimal (`0x1F`, `$1F`) or binary (`0b101`) and can
+/// refer to labels and constants, optionally adding or subtracting other values (`label+2`).
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(idx, text)| Line::parse(text).map_err(|msg| AsmError::new(idx + 1, msg)))
+        .collect::<Result<Vec<Line>, AsmError>>()?;
+
+    let symbols = symbols(&lines)?;
+
+    let mut rom = Vec::new();
+    for (idx, line) in lines.iter().enumerate() {
+        let addr = START as usize + rom.len();
+        let err = |msg| AsmError::new(idx + 1, msg);
+        if let Some(stmt) = &line.statement {
+            stmt.encode(addr as u16, &symbols, &mut rom).map_err(err)?;
+        }
+    }
+    Ok(rom)
+}
+
+// ------ //
+// Errors //
+// ------ //
+
+#[derive(Debug)]
+pub struct AsmError {
+    line: usize,
+    message: String,
+}
+
+impl AsmError {
+    fn new(line: usize, message: String) -> AsmError {
+        AsmError { line, message }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+// ------- //
+// Parsing //
+// ------- //
+
+struct Line {
+    label: Option<String>,
+    statement: Option<Statement>,
+}
+
+enum Statement {
+    Equ(String, Expr),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+    Instruction(String, Vec<Operand>),
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Reg(u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Value(Expr),
+}
+
+/// A sum of numbers and symbols.
+#[derive(Clone, Debug)]
+struct Expr(Vec<(bool, Term)>);
+
+#[derive(Clone, Debug)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+impl Line {
+    fn parse(text: &str) -> Result<Line, String> {
+        let mut text = text.split(';').next().unwrap_or_default().trim();
+        let mut label = None;
+
+        if let Some((name, rest)) = text.split_once(':') {
+            label = Some(parse_symbol(name.trim())?);
+            text = rest.trim();
+        }
+
+        let tokens: Vec<&str> = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+            .collect();
+
+        let statement = match tokens.as_slice() {
+            [] => None,
+            [name, equ, value] if equ.eq_ignore_ascii_case("equ") => {
+                Some(Statement::Equ(parse_symbol(name)?, Expr::parse(value)?))
+            }
+            [directive, values @ ..] if directive.eq_ignore_ascii_case("db") => {
+                Some(Statement::Bytes(parse_values(values)?))
+            }
+            [directive, values @ ..] if directive.eq_ignore_ascii_case("dw") => {
+                Some(Statement::Words(parse_values(values)?))
+            }
+            [mnemonic, operands @ ..] => {
+                let operands = operands
+                    .iter()
+                    .map(|op| Operand::parse(op))
+                    .collect::<Result<_, _>>()?;
+                Some(Statement::Instruction(
+                    mnemonic.to_ascii_uppercase(),
+                    operands,
+                ))
+            }
+        };
+
+        Ok(Line { label, statement })
+    }
+}
+
+fn parse_values(values: &[&str]) -> Result<Vec<Expr>, String> {
+    values.iter().map(|value| Expr::parse(value)).collect()
+}
+
+fn parse_symbol(name: &str) -> Result<String, String> {
+    let valid_start = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    if valid_start && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Ok(name.to_string())
+    } else {
+        Err(format!("`{}` is not a valid name", name))
+    }
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Operand, String> {
+        let op = match text.to_ascii_uppercase().as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            reg if reg.len() == 2 && reg.starts_with('V') => {
+                match u8::from_str_radix(&reg[1..], 16) {
+                    Ok(idx) => Operand::Reg(idx),
+                    Err(_) => Operand::Value(Expr::parse(text)?),
+                }
+            }
+            _ => Operand::Value(Expr::parse(text)?),
+        };
+        Ok(op)
+    }
+}
+
+impl Expr {
+    fn parse(text: &str) -> Result<Expr, String> {
+        let mut terms = Vec::new();
+        let mut positive = true;
+        let mut start = 0;
+
+        // Split on + and -, a leading sign applies to the first term
+        for (idx, c) in text.char_indices() {
+            if c == '+' || c == '-' {
+                if idx > start {
+                    terms.push((positive, Term::parse(&text[start..idx])?));
+                } else if idx > 0 {
+                    return Err(format!("`{}` is not a valid value", text));
+                }
+                positive = c == '+';
+                start = idx + 1;
+            }
+        }
+        terms.push((positive, Term::parse(&text[start..])?));
+
+        Ok(Expr(terms))
+    }
+
+    fn eval(&self, symbols: &HashMap<String, i64>) -> Result<i64, String> {
+        let mut res = 0;
+        for (positive, term) in &self.0 {
+            let val = match term {
+                Term::Number(val) => *val,
+                Term::Symbol(name) => *symbols
+                    .get(name)
+                    .ok_or_else(|| format!("`{}` is not defined", name))?,
+            };
+            res += if *positive { val } else { -val };
+        }
+        Ok(res)
+    }
+}
+
+impl Term {
+    fn parse(text: &str) -> Result<Term, String> {
+        let lower = text.to_ascii_lowercase();
+        let number = if let Some(hex) = lower.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(hex) = lower.strip_prefix('$') {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(bin) = lower.strip_prefix("0b") {
+            i64::from_str_radix(bin, 2)
+        } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+            lower.parse()
+        } else {
+            return parse_symbol(text).map(Term::Symbol);
+        };
+
+        number
+            .map(Term::Number)
+            .map_err(|_| format!("`{}` is not a valid number", text))
+    }
+}
+
+// ------- //
+// Symbols //
+// ------- //
+
+/// Find the address of every label and the value of every constant.
+fn symbols(lines: &[Line]) -> Result<HashMap<String, i64>, AsmError> {
+    let mut symbols = HashMap::new();
+    let mut addr = START as i64;
+
+    for (idx, line) in lines.iter().enumerate() {
+        let err = |msg| AsmError::new(idx + 1, msg);
+        let define = |symbols: &mut HashMap<_, _>, name: &String, val| match symbols
+            .insert(name.clone(), val)
+        {
+            Some(_) => Err(err(format!("`{}` is defined more than once", name))),
+            None => Ok(()),
+        };
+
+        if let Some(label) = &line.label {
+            define(&mut symbols, label, addr)?;
+        }
+
+        match &line.statement {
+            // Constants can only refer to symbols defined before them
+            Some(Statement::Equ(name, expr)) => {
+                let val = expr.eval(&symbols).map_err(err)?;
+                define(&mut symbols, name, val)?;
+            }
+            Some(stmt) => addr += stmt.size() as i64,
+            None => (),
+        }
+    }
+
+    Ok(symbols)
+}
+
+// -------- //
+// Encoding //
+// -------- //
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Equ(_, _) => 0,
+            Statement::Bytes(values) => values.len(),
+            Statement::Words(values) => values.len() * 2,
+            Statement::Instruction(name, _) if name == "LONG" => 4,
+            Statement::Instruction(_, _) => 2,
+        }
+    }
+
+    fn encode(
+        &self,
+        addr: u16,
+        symbols: &HashMap<String, i64>,
+        rom: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        if addr as usize + self.size() > 0x10000 {
+            return Err(String::from("program does not fit in memory"));
+        }
+
+        match self {
+            Statement::Equ(_, _) => (),
+            Statement::Bytes(values) => {
+                for val in values {
+                    rom.push(fit(val.eval(symbols)?, 8)? as u8);
+                }
+            }
+            Statement::Words(values) => {
+                for val in values {
+                    rom.extend_from_slice(&fit(val.eval(symbols)?, 16)?.to_be_bytes());
+                }
+            }
+            Statement::Instruction(name, operands) => {
+                let eval = |op: &Operand| match op {
+                    Operand::Value(expr) => expr.eval(symbols),
+                    _ => Err(String::from("expected a value")),
+                };
+
+                if name == "LONG" {
+                    let addr = match operands.as_slice() {
+                        [op] => fit(eval(op)?, 16)?,
+                        _ => return Err(String::from("`LONG` expects an address")),
+                    };
+                    rom.extend_from_slice(&0xF000u16.to_be_bytes());
+                    rom.extend_from_slice(&addr.to_be_bytes());
+                } else {
+                    let code = encode(name, operands, &eval)?;
+                    rom.extend_from_slice(&code.to_be_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check if `val` fits in `bits`, negative values are stored in two's complement.
+fn fit(val: i64, bits: u32) -> Result<u16, String> {
+    let max = (1 << bits) - 1;
+    if val > max || val < -(1 << (bits - 1)) {
+        return Err(format!("{:#X} does not fit in {} bits", val, bits));
+    }
+    Ok((val & max) as u16)
+}
+
+const MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU", "SYS", "JP", "CALL",
+    "SE", "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL",
+    "RND", "DRW", "SKP", "SKNP", "LONG", "PLANE", "PITCH",
+];
+
+#[rustfmt::skip]
+fn encode<F>(name: &str, operands: &[Operand], eval: &F) -> Result<u16, String>
+where
+    F: Fn(&Operand) -> Result<i64, String>,
+{
+    use Operand::*;
+
+    let nnn = |op| eval(op).and_then(|val| fit(val, 12));
+    let kk = |op| eval(op).and_then(|val| fit(val, 8));
+    let n = |op| eval(op).and_then(|val| fit(val, 4));
+    let x = |reg: &u8| (*reg as u16 & 0xF) << 8;
+    let y = |reg: &u8| (*reg as u16 & 0xF) << 4;
+
+    let code = match (name, operands) {
+        ("CLS", [])                => 0x00E0,
+        ("RET", [])                => 0x00EE,
+        ("SCR", [])                => 0x00FB,
+        ("SCL", [])                => 0x00FC,
+        ("EXIT", [])               => 0x00FD,
+        ("LOW", [])                => 0x00FE,
+        ("HIGH", [])               => 0x00FF,
+        ("AUDIO", [])              => 0xF002,
+        ("SCD", [op])              => 0x00C0 | n(op)?,
+        ("SCU", [op])              => 0x00D0 | n(op)?,
+        ("SYS", [op])              => nnn(op)?,
+        ("JP", [Reg(0), op])       => 0xB000 | nnn(op)?,
+        ("JP", [op])               => 0x1000 | nnn(op)?,
+        ("CALL", [op])             => 0x2000 | nnn(op)?,
+        ("SE", [Reg(r), Reg(s)])   => 0x5000 | x(r) | y(s),
+        ("SE", [Reg(r), op])       => 0x3000 | x(r) | kk(op)?,
+        ("SNE", [Reg(r), Reg(s)])  => 0x9000 | x(r) | y(s),
+        ("SNE", [Reg(r), op])      => 0x4000 | x(r) | kk(op)?,
+        ("SAVE", [Reg(r), Reg(s)]) => 0x5002 | x(r) | y(s),
+        ("LOAD", [Reg(r), Reg(s)]) => 0x5003 | x(r) | y(s),
+        ("LD", [Reg(r), Reg(s)])   => 0x8000 | x(r) | y(s),
+        ("LD", [Reg(r), Dt])       => 0xF007 | x(r),
+        ("LD", [Reg(r), K])        => 0xF00A | x(r),
+        ("LD", [Reg(r), IndirectI])=> 0xF065 | x(r),
+        ("LD", [Reg(r), R])        => 0xF085 | x(r),
+        ("LD", [Reg(r), op])       => 0x6000 | x(r) | kk(op)?,
+        ("LD", [I, op])            => 0xA000 | nnn(op)?,
+        ("LD", [Dt, Reg(r)])       => 0xF015 | x(r),
+        ("LD", [St, Reg(r)])       => 0xF018 | x(r),
+        ("LD", [F, Reg(r)])        => 0xF029 | x(r),
+        ("LD", [Hf, Reg(r)])       => 0xF030 | x(r),
+        ("LD", [B, Reg(r)])        => 0xF033 | x(r),
+        ("LD", [IndirectI, Reg(r)])=> 0xF055 | x(r),
+        ("LD", [R, Reg(r)])        => 0xF075 | x(r),
+        ("ADD", [Reg(r), Reg(s)])  => 0x8004 | x(r) | y(s),
+        ("ADD", [Reg(r), op])      => 0x7000 | x(r) | kk(op)?,
+        ("ADD", [I, Reg(r)])       => 0xF01E | x(r),
+        ("OR", [Reg(r), Reg(s)])   => 0x8001 | x(r) | y(s),
+        ("AND", [Reg(r), Reg(s)])  => 0x8002 | x(r) | y(s),
+        ("XOR", [Reg(r), Reg(s)])  => 0x8003 | x(r) | y(s),
+        ("SUB", [Reg(r), Reg(s)])  => 0x8005 | x(r) | y(s),
+        ("SHR", [Reg(r), Reg(s)])  => 0x8006 | x(r) | y(s),
+        ("SHR", [Reg(r)])          => 0x8006 | x(r) | y(r),
+        ("SUBN", [Reg(r), Reg(s)]) => 0x8007 | x(r) | y(s),
+        ("SHL", [Reg(r), Reg(s)])  => 0x800E | x(r) | y(s),
+        ("SHL", [Reg(r)])          => 0x800E | x(r) | y(r),
+        ("RND", [Reg(r), op])      => 0xC000 | x(r) | kk(op)?,
+        ("DRW", [Reg(r), Reg(s), op]) => 0xD000 | x(r) | y(s) | n(op)?,
+        ("SKP", [Reg(r)])          => 0xE09E | x(r),
+        ("SKNP", [Reg(r)])         => 0xE0A1 | x(r),
+        ("PLANE", [op])            => 0xF001 | n(op)? << 8,
+        ("PITCH", [Reg(r)])        => 0xF03A | x(r),
+        _ if MNEMONICS.contains(&name) => {
+            return Err(format!("`{}` does not accept these operands", name))
+        }
+        _ => return Err(format!("Unknown instruction `{}`", name)),
+    };
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::super::disasm::{self, Syntax};
+    use super::super::Platform;
+    use super::assemble;
+
+    // Line of the error produced by assembling `source`
+    fn error_line(source: &str) -> usize {
+        assemble(source).unwrap_err().line
+    }
+
+    // Round trip
+    // ----------
+
+    #[test]
+    fn disassembled_roms_assemble_to_the_same_bytes() {
+        let platforms = [Platform::Chip8, Platform::SuperChip, Platform::XoChip];
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        for platform in platforms.iter() {
+            for _ in 0..300 {
+                let len = rng.random_range(0..256);
+                let rom: Vec<u8> = (0..len).map(|_| rng.random()).collect();
+
+                let listing = disasm::disassemble(&rom, *platform, Syntax::Classic);
+                let assembled =
+                    assemble(&listing).unwrap_or_else(|e| panic!("{} in listing:\n{}", e, listing));
+                assert_eq!(assembled, rom, "listing:\n{}", listing);
+            }
+        }
+    }
+
+    // Symbols and values
+    // ------------------
+
+    #[test]
+    fn equ_defines_constants() {
+        let source = "five EQU 5\nsix EQU five+1\nLD v0, five\nLD v1, six";
+        assert_eq!(assemble(source).unwrap(), vec![0x60, 0x05, 0x61, 0x06]);
+    }
+
+    #[test]
+    fn labels_can_be_used_before_they_are_defined() {
+        let source = "JP end\nCLS\nend: RET";
+        let rom = assemble(source).unwrap();
+        assert_eq!(rom, vec![0x12, 0x04, 0x00, 0xE0, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn expressions_add_and_subtract_values() {
+        let source = "start: JP start+4-2\nDB 1, -1, 0b101, $1F, 0x10-1\nDW 0x1234";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            rom,
+            vec![0x12, 0x02, 0x01, 0xFF, 0x05, 0x1F, 0x0F, 0x12, 0x34]
+        );
+    }
+
+    #[test]
+    fn mnemonics_and_registers_are_case_insensitive() {
+        let rom = assemble("ld VA, 0X12 ; comment\nDrw v1, V2, 3").unwrap();
+        assert_eq!(rom, vec![0x6A, 0x12, 0xD1, 0x23]);
+    }
+
+    #[test]
+    fn long_takes_up_four_bytes() {
+        let rom = assemble("LONG end\nend: CLS").unwrap();
+        assert_eq!(rom, vec![0xF0, 0x00, 0x02, 0x04, 0x00, 0xE0]);
+    }
+
+    #[test]
+    fn extension_opcodes() {
+        let source = "SCD 4\nSCU 2\nHIGH\nEXIT\nPLANE 3\nSAVE v1, v2\nLOAD v3, v4\n\
+                      LD HF, v3\nLD R, v7\nLD v7, R\nAUDIO\nPITCH v2";
+        let rom = assemble(source).unwrap();
+        assert_eq!(
+            rom,
+            vec![
+                0x00, 0xC4, 0x00, 0xD2, 0x00, 0xFF, 0x00, 0xFD, 0xF3, 0x01, 0x51, 0x22, 0x53, 0x43,
+                0xF3, 0x30, 0xF7, 0x75, 0xF7, 0x85, 0xF0, 0x02, 0xF2, 0x3A,
+            ]
+        );
+    }
+
+    // Errors
+    // ------
+
+    #[test]
+    fn undefined_symbols_are_reported() {
+        assert_eq!(error_line("CLS\nJP nowhere"), 2);
+        assert_eq!(error_line("a EQU b\nb EQU 1"), 1);
+    }
+
+    #[test]
+    fn labels_are_defined_once_with_valid_names() {
+        assert_eq!(error_line("a: CLS\na: CLS"), 2);
+        assert_eq!(error_line("CLS\n1a: CLS"), 2);
+        assert_eq!(error_line("a EQU 1\na: CLS"), 2);
+    }
+
+    #[test]
+    fn operands_out_of_range_are_rejected() {
+        for source in [
+            "LD v0, 256",
+            "LD v0, -129",
+            "JP 0x1000",
+            "LD I, -2049",
+            "DRW v0, v1, 16",
+            "SCD 16",
+            "DB 256",
+            "DW 0x10000",
+            "LONG 0x10000",
+        ]
+        .iter()
+        {
+            assert!(assemble(source).is_err(), "`{}` was accepted", source);
+        }
+        assert_eq!(assemble("LD v0, -128").unwrap(), vec![0x60, 0x80]);
+    }
+
+    #[test]
+    fn invalid_instructions_are_rejected() {
+        assert_eq!(error_line("CLS\nFOO v0"), 2);
+        assert_eq!(error_line("SE v0, v1, v2"), 1);
+        assert_eq!(error_line("LD v0, 1+"), 1);
+        assert_eq!(error_line("LD v0, 0xZZ"), 1);
+    }
+}
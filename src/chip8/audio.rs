@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use crate::audio::{self, SAMPLE_RATE};
+
+use super::{Chip8, PATTERN_SIZE};
+
+// Volume of the generated square wave
+const AMPLITUDE: i16 = i16::MAX / 4;
+
+// Samples kept for a caller which does not take them: at most one second, the latest half is kept
+const MAX_BUFFERED: usize = SAMPLE_RATE as usize;
+
+// Pattern played by platforms without a pattern buffer: a 500Hz square wave at the default pitch
+const BEEPER: [u8; PATTERN_SIZE] = [0xF0; PATTERN_SIZE];
+
+/// Playback rate of the pattern buffer in bits per second, 4000 at the default pitch of 64.
+fn playback_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+/// Produce the samples for `elapsed` time, during which the sound timer is constant.
+///
+/// While the sound timer is active, the bits of the pattern buffer are played as a square wave,
+/// otherwise silence is produced.
+pub fn synthesize(e: &mut Chip8, elapsed: Duration) {
+    let (count, rest) = audio::sample_count(e.sample_timer + elapsed);
+    e.sample_timer = rest;
+
+    let pattern = if e.platform.xochip() { &e.pattern } else { &BEEPER };
+    let step = playback_rate(e.pitch) / SAMPLE_RATE as f64;
+    let bits = (PATTERN_SIZE * 8) as f64;
+
+    for _ in 0..count {
+        let sample = if e.reg_st > 0 {
+            let bit = e.audio_phase as usize;
+            e.audio_phase = (e.audio_phase + step) % bits;
+
+            if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                AMPLITUDE
+            } else {
+                -AMPLITUDE
+            }
+        } else {
+            0
+        };
+        e.samples.push(sample);
+    }
+
+    if e.samples.len() > MAX_BUFFERED {
+        let excess = e.samples.len() - MAX_BUFFERED / 2;
+        e.samples.drain(..excess);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Chip8, Platform};
+    use super::MAX_BUFFERED;
+    use crate::Emulator;
+
+    #[test]
+    fn samples_which_are_not_taken_stay_bounded() {
+        let mut e = Chip8::new(Platform::Chip8, Platform::Chip8.default_quirks());
+        // LD V0, 0xFF; LD ST, V0; JP 0x200
+        e.load_rom(vec![0x60, 0xFF, 0xF0, 0x18, 0x12, 0x00]);
+
+        // A minute of emulated time, at 500 instructions per second
+        for _ in 0..60 {
+            e.run(500);
+            assert!(e.samples.len() <= MAX_BUFFERED);
+        }
+
+        // The latest samples are kept, and they still play the beeper
+        let samples = e.audio();
+        assert!(samples.len() >= MAX_BUFFERED / 2);
+        assert!(samples.iter().any(|sample| *sample != 0));
+        assert!(e.audio().is_empty());
+    }
+}
@@ -7,6 +7,7 @@ use crate::debug_view::{Frame, Rect};
 
 
 impl crate::debug_view::Debug for Chip8 {
+    #[allow(clippy::needless_borrow)]
     fn debug_view(&self, frame: &mut Frame, rect: Rect) {
         draw_debug(&self, frame, rect)
     }
@@ -23,8 +24,9 @@ fn draw_debug(state: &Chip8, frame: &mut Frame, rect: Rect) {
         .direction(Direction::Vertical)
         .constraints(
             [
-                Constraint::Length(8),
+                Constraint::Length(10),
                 Constraint::Length(4),
+                Constraint::Length(3),
                 Constraint::Percentage(45),
             ]
             .as_ref(),
@@ -34,15 +36,25 @@ fn draw_debug(state: &Chip8, frame: &mut Frame, rect: Rect) {
     draw_memory(state, frame, top[0]);
     draw_registers(state, frame, right[0]);
     draw_stack(state, frame, right[1]);
-    draw_instructions(state, frame, right[2]);
+    draw_breakpoints(state, frame, right[2]);
+    draw_instructions(state, frame, right[3]);
 }
 
 fn draw_instructions(state: &Chip8, frame: &mut Frame, rect: Rect) {
     let rows = rect.height - 3;
+    let text_style = Style::default().fg(Color::White);
+    let break_style = Style::default().fg(Color::Red);
+    let cursor = state.debugger.cursor();
+
+    // Follow the cursor once it leaves the instructions after the program counter
+    let start = match cursor {
+        Some(addr) if addr.wrapping_sub(state.reg_pc) >= rows * 2 => addr,
+        _ => state.reg_pc,
+    };
 
     let instructions = (0..rows).map(|i| {
-        let addr = state.reg_pc + (i * 2);
-        let instruction = state.get_opcode(addr).decode();
+        let addr = start.wrapping_add(i * 2);
+        let instruction = state.get_opcode(addr).decode(state.platform);
 
         let a = format!("${:#05X}", addr);
         let c = format!("({:04X})", instruction.code);
@@ -50,6 +62,7 @@ fn draw_instructions(state: &Chip8, frame: &mut Frame, rect: Rect) {
 
         let v = match instruction.operands {
             Operands::Empty => vec![a, c, n],
+            Operands::Const(cnst) => vec![a, c, n, format!("{:#03X}", cnst)],
             Operands::Address(addr) => vec![a, c, n, format!("${:#03X}", addr)],
             Operands::Reg(reg) => vec![a, c, n, format!("v{:X}", reg)],
             Operands::Regs(regx, regy) => {
@@ -68,7 +81,15 @@ fn draw_instructions(state: &Chip8, frame: &mut Frame, rect: Rect) {
             ],
         };
 
-        Row::Data(v.into_iter())
+        let breakpoint = state.debugger.is_breakpoint(addr);
+        let style = if breakpoint { break_style } else { text_style };
+        if cursor == Some(addr) {
+            Row::StyledData(v.into_iter(), style.modifier(Modifier::REVERSED))
+        } else if breakpoint {
+            Row::StyledData(v.into_iter(), style)
+        } else {
+            Row::Data(v.into_iter())
+        }
     });
 
     let tab = Table::new(
@@ -91,6 +112,7 @@ fn draw_instructions(state: &Chip8, frame: &mut Frame, rect: Rect) {
     frame.render_widget(tab, rect);
 }
 
+#[allow(clippy::redundant_pattern_matching)]
 fn draw_registers(state: &Chip8, frame: &mut Frame, rect: Rect) {
     let mut regs: Vec<Text> = Vec::with_capacity(50);
 
@@ -126,6 +148,25 @@ fn draw_registers(state: &Chip8, frame: &mut Frame, rect: Rect) {
         regs.push(Text::styled(format!("          (v{:X})", r), wait_style));
     };
 
+    regs.push(Text::raw("\n"));
+
+    regs.push(Text::styled("Seed ", name_style));
+    regs.push(Text::raw(format!("{}", state.seed)));
+    if state.vip_random() {
+        regs.push(Text::raw(" (vip)"));
+    }
+
+    regs.push(Text::raw("\n"));
+
+    let (ips, per_frame) = state.speed();
+    let speed = if state.frame_locked() {
+        format!("{:.0} ips ({:.0}/frame, locked)", ips, per_frame)
+    } else {
+        format!("{:.0} ips ({:.1}/frame)", ips, per_frame)
+    };
+    regs.push(Text::styled("Speed ", name_style));
+    regs.push(Text::raw(speed));
+
     let par = Paragraph::new(regs.iter())
         .block(Block::default().title("Registers").borders(Borders::ALL))
         .alignment(tui::layout::Alignment::Left);
@@ -151,6 +192,22 @@ fn draw_stack(state: &Chip8, frame: &mut Frame, rect: Rect) {
     frame.render_widget(par, rect);
 }
 
+fn draw_breakpoints(state: &Chip8, frame: &mut Frame, rect: Rect) {
+    let breakpoints = state
+        .debugger
+        .breakpoints()
+        .map(|addr| format!("{:#05X}", addr));
+    let watchpoints = state.debugger.watchpoints().iter().map(|w| w.to_string());
+    let points: Vec<String> = breakpoints.chain(watchpoints).collect();
+    let text = [Text::raw(points.join(" "))];
+
+    let par = Paragraph::new(text.iter())
+        .block(Block::default().title("Breakpoints").borders(Borders::ALL))
+        .alignment(tui::layout::Alignment::Left);
+
+    frame.render_widget(par, rect);
+}
+
 fn draw_memory(state: &Chip8, frame: &mut Frame, rect: Rect) {
     let mut constraints = [Constraint::Length(3); 17];
     constraints[0] = Constraint::Length(8);
@@ -162,12 +219,12 @@ fn draw_memory(state: &Chip8, frame: &mut Frame, rect: Rect) {
     }
 
     let rows = (0..(rect.height - 3)).map(move |row_idx| {
-        let start_addr = state.reg_i - (state.reg_i % 0x10) + (row_idx * 16);
+        let start_addr = (state.reg_i - (state.reg_i % 0x10)).wrapping_add(row_idx * 16);
         let mut vec: Vec<String> = Vec::with_capacity(17);
         vec.push(format!("${:#06X}", start_addr));
 
-        for addr in start_addr..(start_addr + 16) {
-            vec.push(format!("{:02X}", state.mem[addr]));
+        for offset in 0..16 {
+            vec.push(format!("{:02X}", state.mem[start_addr.wrapping_add(offset)]));
         }
 
         Row::Data(vec.into_iter())
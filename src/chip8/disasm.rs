@@ -0,0 +1,536 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+
+use super::opcode::OpCode;
+use super::Platform;
+
+// Roms are loaded at this address
+const START: u16 = 0x200;
+
+// Amount of data bytes placed on a single line
+const BYTES_PER_LINE: usize = 8;
+
+/// Notation of the disassembled instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    /// The high level syntax of the Octo assembler (`v0 := 0x12`, `if v0 == v1 then`,...)
+    Octo,
+    /// The mnemonics of Cowgod's Chip-8 technical reference (`LD v0, 0x12`, `SE v0, v1`,...)
+    Classic,
+}
+
+/// Disassemble a rom into a listing which can be assembled again.
+///
+/// Instructions are only disassembled if they are reachable from the start of the rom, every
+/// other byte is considered to be data. Targets of jumps, calls and `I` loads inside the rom
+/// get a label. The address and opcode of every line is added as a comment.
+pub fn disassemble(rom: &[u8], platform: Platform, syntax: Syntax) -> String {
+    // Anything past the end of memory is never loaded
+    let rom = &rom[..rom.len().min(platform.mem_size() - START as usize)];
+    let mut listing = Listing::new(rom, platform, syntax);
+    listing.trace();
+    listing.render()
+}
+
+// ------------ //
+// Reachability //
+// ------------ //
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Data,
+    Jump,
+    Call,
+}
+
+struct Listing<'a> {
+    rom: &'a [u8],
+    platform: Platform,
+    syntax: Syntax,
+    // Length of the instruction starting at every offset of the rom, if any
+    code: Vec<Option<u16>>,
+    labels: BTreeMap<u16, Label>,
+}
+
+impl<'a> Listing<'a> {
+    fn new(rom: &'a [u8], platform: Platform, syntax: Syntax) -> Listing<'a> {
+        Listing {
+            rom,
+            platform,
+            syntax,
+            code: vec![None; rom.len()],
+            labels: BTreeMap::new(),
+        }
+    }
+
+    fn offset(&self, addr: u16) -> Option<usize> {
+        let offset = addr.checked_sub(START)? as usize;
+        if offset < self.rom.len() {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    fn byte(&self, addr: u16) -> Option<u8> {
+        self.offset(addr).map(|offset| self.rom[offset])
+    }
+
+    fn word(&self, addr: u16) -> Option<u16> {
+        let hi = self.byte(addr)? as u16;
+        let lo = self.byte(addr.wrapping_add(1))? as u16;
+        Some(hi << 8 | lo)
+    }
+
+    /// Length of the valid instruction at `addr`, if there is one.
+    fn instruction_len(&self, addr: u16) -> Option<u16> {
+        let code = self.word(addr)?;
+        let ins = OpCode::from_cells((code >> 8) as u8, code as u8).decode(self.platform);
+
+        match ins.name {
+            // Machine code routines and unknown opcodes are most likely data
+            "SYS" | "???" => None,
+            // F000 nnnn is followed by a 16 bit address
+            "LONG" => self.word(addr.wrapping_add(2)).map(|_| 4),
+            _ => Some(2),
+        }
+    }
+
+    fn label(&mut self, addr: u16, label: Label) {
+        if self.offset(addr).is_some() {
+            let entry = self.labels.entry(addr).or_insert(label);
+            *entry = (*entry).max(label);
+        }
+    }
+
+    /// Follow every path through the program, starting at the first instruction.
+    fn trace(&mut self) {
+        let mut todo = VecDeque::new();
+        todo.push_back(START);
+
+        while let Some(addr) = todo.pop_front() {
+            let offset = match self.offset(addr) {
+                Some(offset) if self.code[offset].is_none() => offset,
+                _ => continue,
+            };
+            let len = match self.instruction_len(addr) {
+                Some(len) => len,
+                None => continue,
+            };
+            self.code[offset] = Some(len);
+
+            let code = self.word(addr).unwrap_or_default();
+            let next = addr.wrapping_add(len);
+            let nnn = code & 0x0FFF;
+
+            match (code >> 12, code & 0x00FF) {
+                // RET, EXIT
+                (0x0, 0xEE) | (0x0, 0xFD) => (),
+                // JP nnn
+                (0x1, _) => {
+                    self.label(nnn, Label::Jump);
+                    todo.push_back(nnn);
+                }
+                // CALL nnn
+                (0x2, _) => {
+                    self.label(nnn, Label::Call);
+                    todo.push_back(nnn);
+                    todo.push_back(next);
+                }
+                // Skips, the skipped instruction may be a 4 byte instruction
+                _ if is_skip(code) => {
+                    todo.push_back(next);
+                    let skipped = self.instruction_len(next).unwrap_or(2);
+                    todo.push_back(next.wrapping_add(skipped));
+                }
+                // LD I, nnn
+                (0xA, _) => {
+                    self.label(nnn, Label::Data);
+                    todo.push_back(next);
+                }
+                // JP V0, nnn: the offset is unknown, assume the target is a jump table
+                (0xB, _) => {
+                    self.label(nnn, Label::Jump);
+                    todo.push_back(nnn);
+                }
+                // LONG nnnn
+                (0xF, 0x00) if len == 4 => {
+                    let target = self.word(addr.wrapping_add(2)).unwrap_or_default();
+                    self.label(target, Label::Data);
+                    todo.push_back(next);
+                }
+                _ => todo.push_back(next),
+            }
+        }
+    }
+}
+
+fn is_skip(code: u16) -> bool {
+    matches!(
+        (code >> 12, code & 0x000F),
+        (0x3, _) | (0x4, _) | (0xE, _) | (0x5, 0x0) | (0x9, 0x0)
+    )
+}
+
+// --------- //
+// Rendering //
+// --------- //
+
+enum Item {
+    Code(u16, u16),
+    Data(u16, usize),
+}
+
+impl<'a> Listing<'a> {
+    /// Split the rom into instructions and lines of data.
+    fn items(&self) -> Vec<Item> {
+        let mut items = Vec::new();
+        let mut offset = 0;
+
+        while offset < self.rom.len() {
+            let addr = START + offset as u16;
+
+            if let Some(len) = self.code[offset] {
+                items.push(Item::Code(addr, len));
+                offset += len as usize;
+                continue;
+            }
+
+            // Data lines end at the next instruction or label
+            let mut len = 1;
+            while len < BYTES_PER_LINE
+                && offset + len < self.rom.len()
+                && self.code[offset + len].is_none()
+                && !self.labels.contains_key(&(addr + len as u16))
+            {
+                len += 1;
+            }
+            items.push(Item::Data(addr, len));
+            offset += len;
+        }
+
+        items
+    }
+
+    fn render(&mut self) -> String {
+        let items = self.items();
+
+        // Labels in the middle of an instruction cannot be placed
+        let starts: Vec<u16> = items
+            .iter()
+            .map(|item| match item {
+                Item::Code(addr, _) | Item::Data(addr, _) => *addr,
+            })
+            .collect();
+        self.labels
+            .retain(|addr, _| starts.binary_search(addr).is_ok());
+
+        // Octo refuses programs without a `main` label, it marks where they start
+        if self.syntax == Syntax::Octo && !self.rom.is_empty() {
+            self.labels.entry(START).or_insert(Label::Jump);
+        }
+
+        let mut out = String::new();
+        for item in items {
+            let (addr, len, text) = match item {
+                Item::Code(addr, len) => (addr, len as usize, self.instruction(addr)),
+                Item::Data(addr, len) => (addr, len, self.data(addr, len)),
+            };
+
+            if let Some(name) = self.label_name(addr) {
+                match self.syntax {
+                    Syntax::Octo => writeln!(out, ": {}", name).unwrap(),
+                    Syntax::Classic => writeln!(out, "{}:", name).unwrap(),
+                }
+            }
+
+            let offset = (addr - START) as usize;
+            let bytes: String = self.rom[offset..offset + len]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            let comment = match self.syntax {
+                Syntax::Octo => '#',
+                Syntax::Classic => ';',
+            };
+            writeln!(out, "    {:<31} {} {:03X}: {}", text, comment, addr, bytes).unwrap();
+        }
+        out
+    }
+
+    fn label_name(&self, addr: u16) -> Option<String> {
+        let prefix = match self.labels.get(&addr)? {
+            _ if addr == START && self.syntax == Syntax::Octo => return Some(String::from("main")),
+            Label::Data => "data",
+            Label::Jump => "label",
+            Label::Call => "sub",
+        };
+        Some(format!("{}_{:03X}", prefix, addr))
+    }
+
+    /// A label for `addr` if it has one, the address itself otherwise.
+    fn target(&self, addr: u16) -> String {
+        self.label_name(addr)
+            .unwrap_or_else(|| format!("{:#05X}", addr))
+    }
+
+    fn data(&self, addr: u16, len: usize) -> String {
+        let offset = (addr - START) as usize;
+        let bytes: Vec<String> = self.rom[offset..offset + len]
+            .iter()
+            .map(|byte| format!("{:#04X}", byte))
+            .collect();
+
+        match self.syntax {
+            Syntax::Octo => bytes.join(" "),
+            Syntax::Classic => format!("DB {}", bytes.join(", ")),
+        }
+    }
+
+    fn instruction(&self, addr: u16) -> String {
+        let code = self.word(addr).unwrap_or_default();
+        let long = self.word(addr.wrapping_add(2)).unwrap_or_default();
+        match self.syntax {
+            Syntax::Octo => self.octo(code, long),
+            Syntax::Classic => self.classic(code, long),
+        }
+    }
+
+    #[rustfmt::skip]
+    fn classic(&self, code: u16, long: u16) -> String {
+        let (x, y, n) = ((code >> 8) & 0xF, (code >> 4) & 0xF, code & 0xF);
+        let (nnn, kk) = (code & 0x0FFF, code & 0x00FF);
+
+        match (code >> 12, x, y, n) {
+            (0x0, 0, 0xC, _)   => format!("SCD {:#03X}", n),
+            (0x0, 0, 0xD, _)   => format!("SCU {:#03X}", n),
+            (0x0, 0, 0xE, 0x0) => String::from("CLS"),
+            (0x0, 0, 0xE, 0xE) => String::from("RET"),
+            (0x0, 0, 0xF, 0xB) => String::from("SCR"),
+            (0x0, 0, 0xF, 0xC) => String::from("SCL"),
+            (0x0, 0, 0xF, 0xD) => String::from("EXIT"),
+            (0x0, 0, 0xF, 0xE) => String::from("LOW"),
+            (0x0, 0, 0xF, 0xF) => String::from("HIGH"),
+            (0x1, _, _, _)     => format!("JP {}", self.target(nnn)),
+            (0x2, _, _, _)     => format!("CALL {}", self.target(nnn)),
+            (0x3, _, _, _)     => format!("SE v{:X}, {:#04X}", x, kk),
+            (0x4, _, _, _)     => format!("SNE v{:X}, {:#04X}", x, kk),
+            (0x5, _, _, 0x0)   => format!("SE v{:X}, v{:X}", x, y),
+            (0x5, _, _, 0x2)   => format!("SAVE v{:X}, v{:X}", x, y),
+            (0x5, _, _, 0x3)   => format!("LOAD v{:X}, v{:X}", x, y),
+            (0x6, _, _, _)     => format!("LD v{:X}, {:#04X}", x, kk),
+            (0x7, _, _, _)     => format!("ADD v{:X}, {:#04X}", x, kk),
+            (0x8, _, _, 0x0)   => format!("LD v{:X}, v{:X}", x, y),
+            (0x8, _, _, 0x1)   => format!("OR v{:X}, v{:X}", x, y),
+            (0x8, _, _, 0x2)   => format!("AND v{:X}, v{:X}", x, y),
+            (0x8, _, _, 0x3)   => format!("XOR v{:X}, v{:X}", x, y),
+            (0x8, _, _, 0x4)   => format!("ADD v{:X}, v{:X}", x, y),
+            (0x8, _, _, 0x5)   => format!("SUB v{:X}, v{:X}", x, y),
+            (0x8, _, _, 0x6)   => format!("SHR v{:X}, v{:X}", x, y),
+            (0x8, _, _, 0x7)   => format!("SUBN v{:X}, v{:X}", x, y),
+            (0x8, _, _, 0xE)   => format!("SHL v{:X}, v{:X}", x, y),
+            (0x9, _, _, 0x0)   => format!("SNE v{:X}, v{:X}", x, y),
+            (0xA, _, _, _)     => format!("LD I, {}", self.target(nnn)),
+            (0xB, _, _, _)     => format!("JP v0, {}", self.target(nnn)),
+            (0xC, _, _, _)     => format!("RND v{:X}, {:#04X}", x, kk),
+            (0xD, _, _, _)     => format!("DRW v{:X}, v{:X}, {:#03X}", x, y, n),
+            (0xE, _, 0x9, 0xE) => format!("SKP v{:X}", x),
+            (0xE, _, 0xA, 0x1) => format!("SKNP v{:X}", x),
+            (0xF, 0, 0x0, 0x0) => format!("LONG {}", self.long_target(long)),
+            (0xF, _, 0x0, 0x1) => format!("PLANE {:#03X}", x),
+            (0xF, 0, 0x0, 0x2) => String::from("AUDIO"),
+            (0xF, _, 0x0, 0x7) => format!("LD v{:X}, DT", x),
+            (0xF, _, 0x0, 0xA) => format!("LD v{:X}, K", x),
+            (0xF, _, 0x1, 0x5) => format!("LD DT, v{:X}", x),
+            (0xF, _, 0x1, 0x8) => format!("LD ST, v{:X}", x),
+            (0xF, _, 0x1, 0xE) => format!("ADD I, v{:X}", x),
+            (0xF, _, 0x2, 0x9) => format!("LD F, v{:X}", x),
+            (0xF, _, 0x3, 0x0) => format!("LD HF, v{:X}", x),
+            (0xF, _, 0x3, 0x3) => format!("LD B, v{:X}", x),
+            (0xF, _, 0x3, 0xA) => format!("PITCH v{:X}", x),
+            (0xF, _, 0x5, 0x5) => format!("LD [I], v{:X}", x),
+            (0xF, _, 0x6, 0x5) => format!("LD v{:X}, [I]", x),
+            (0xF, _, 0x7, 0x5) => format!("LD R, v{:X}", x),
+            (0xF, _, 0x8, 0x5) => format!("LD v{:X}, R", x),
+            _ => unreachable!("{:04X} is not an instruction", code),
+        }
+    }
+
+    #[rustfmt::skip]
+    fn octo(&self, code: u16, long: u16) -> String {
+        let (x, y, n) = ((code >> 8) & 0xF, (code >> 4) & 0xF, code & 0xF);
+        let (nnn, kk) = (code & 0x0FFF, code & 0x00FF);
+
+        match (code >> 12, x, y, n) {
+            (0x0, 0, 0xC, _)   => format!("scroll-down {}", n),
+            (0x0, 0, 0xD, _)   => format!("scroll-up {}", n),
+            (0x0, 0, 0xE, 0x0) => String::from("clear"),
+            (0x0, 0, 0xE, 0xE) => String::from("return"),
+            (0x0, 0, 0xF, 0xB) => String::from("scroll-right"),
+            (0x0, 0, 0xF, 0xC) => String::from("scroll-left"),
+            (0x0, 0, 0xF, 0xD) => String::from("exit"),
+            (0x0, 0, 0xF, 0xE) => String::from("lores"),
+            (0x0, 0, 0xF, 0xF) => String::from("hires"),
+            (0x1, _, _, _)     => format!("jump {}", self.target(nnn)),
+            (0x2, _, _, _)     => match self.label_name(nnn) {
+                Some(name) => name,
+                None => format!(":call {:#05X}", nnn),
+            },
+            (0x3, _, _, _)     => format!("if v{:x} != {:#04X} then", x, kk),
+            (0x4, _, _, _)     => format!("if v{:x} == {:#04X} then", x, kk),
+            (0x5, _, _, 0x0)   => format!("if v{:x} != v{:x} then", x, y),
+            (0x5, _, _, 0x2)   => format!("save v{:x} - v{:x}", x, y),
+            (0x5, _, _, 0x3)   => format!("load v{:x} - v{:x}", x, y),
+            (0x6, _, _, _)     => format!("v{:x} := {:#04X}", x, kk),
+            (0x7, _, _, _)     => format!("v{:x} += {:#04X}", x, kk),
+            (0x8, _, _, 0x0)   => format!("v{:x} := v{:x}", x, y),
+            (0x8, _, _, 0x1)   => format!("v{:x} |= v{:x}", x, y),
+            (0x8, _, _, 0x2)   => format!("v{:x} &= v{:x}", x, y),
+            (0x8, _, _, 0x3)   => format!("v{:x} ^= v{:x}", x, y),
+            (0x8, _, _, 0x4)   => format!("v{:x} += v{:x}", x, y),
+            (0x8, _, _, 0x5)   => format!("v{:x} -= v{:x}", x, y),
+            (0x8, _, _, 0x6)   => format!("v{:x} >>= v{:x}", x, y),
+            (0x8, _, _, 0x7)   => format!("v{:x} =- v{:x}", x, y),
+            (0x8, _, _, 0xE)   => format!("v{:x} <<= v{:x}", x, y),
+            (0x9, _, _, 0x0)   => format!("if v{:x} == v{:x} then", x, y),
+            (0xA, _, _, _)     => format!("i := {}", self.target(nnn)),
+            (0xB, _, _, _)     => format!("jump0 {}", self.target(nnn)),
+            (0xC, _, _, _)     => format!("v{:x} := random {:#04X}", x, kk),
+            (0xD, _, _, _)     => format!("sprite v{:x} v{:x} {}", x, y, n),
+            (0xE, _, 0x9, 0xE) => format!("if v{:x} -key then", x),
+            (0xE, _, 0xA, 0x1) => format!("if v{:x} key then", x),
+            (0xF, 0, 0x0, 0x0) => format!("i := long {}", self.long_target(long)),
+            (0xF, _, 0x0, 0x1) => format!("plane {}", x),
+            (0xF, 0, 0x0, 0x2) => String::from("audio"),
+            (0xF, _, 0x0, 0x7) => format!("v{:x} := delay", x),
+            (0xF, _, 0x0, 0xA) => format!("v{:x} := key", x),
+            (0xF, _, 0x1, 0x5) => format!("delay := v{:x}", x),
+            (0xF, _, 0x1, 0x8) => format!("buzzer := v{:x}", x),
+            (0xF, _, 0x1, 0xE) => format!("i += v{:x}", x),
+            (0xF, _, 0x2, 0x9) => format!("i := hex v{:x}", x),
+            (0xF, _, 0x3, 0x0) => format!("i := bighex v{:x}", x),
+            (0xF, _, 0x3, 0x3) => format!("bcd v{:x}", x),
+            (0xF, _, 0x3, 0xA) => format!("pitch := v{:x}", x),
+            (0xF, _, 0x5, 0x5) => format!("save v{:x}", x),
+            (0xF, _, 0x6, 0x5) => format!("load v{:x}", x),
+            (0xF, _, 0x7, 0x5) => format!("saveflags v{:x}", x),
+            (0xF, _, 0x8, 0x5) => format!("loadflags v{:x}", x),
+            _ => unreachable!("{:04X} is not an instruction", code),
+        }
+    }
+
+    fn long_target(&self, addr: u16) -> String {
+        self.label_name(addr)
+            .unwrap_or_else(|| format!("{:#06X}", addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Platform;
+    use super::{disassemble, Syntax};
+
+    // Text of the listing without the address and opcode comments
+    fn lines(rom: &[u8], syntax: Syntax) -> Vec<String> {
+        disassemble(rom, Platform::XoChip, syntax)
+            .lines()
+            .map(|line| line.split(&['#', ';'][..]).next().unwrap())
+            .map(|line| line.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn jump_and_call_targets_get_a_label() {
+        let rom = [
+            0x22, 0x06, // CALL sub_206
+            0x12, 0x04, // JP label_204
+            0x12, 0x04, // JP label_204
+            0x00, 0xEE, // RET
+        ];
+        assert_eq!(
+            lines(&rom, Syntax::Classic),
+            [
+                "CALL sub_206",
+                "JP label_204",
+                "label_204:",
+                "JP label_204",
+                "sub_206:",
+                "RET",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_bytes_are_data() {
+        let rom = [
+            0xA2, 0x06, // LD I, data_206
+            0x12, 0x04, // JP label_204
+            0x12, 0x04, // JP label_204
+            0x60, 0x12, // not reachable, even though it is a valid instruction
+            0xFF,
+        ];
+        assert_eq!(
+            lines(&rom, Syntax::Classic),
+            [
+                "LD I, data_206",
+                "JP label_204",
+                "label_204:",
+                "JP label_204",
+                "data_206:",
+                "DB 0x60, 0x12, 0xFF",
+            ]
+        );
+    }
+
+    #[test]
+    fn skipped_long_instructions_are_code() {
+        let rom = [
+            0x30, 0x00, // SE v0, 0x00
+            0xF0, 0x00, 0x02, 0x08, // LONG data_208
+            0x12, 0x06, // JP label_206
+            0xAA,
+        ];
+        assert_eq!(
+            lines(&rom, Syntax::Classic),
+            [
+                "SE v0, 0x00",
+                "LONG data_208",
+                "label_206:",
+                "JP label_206",
+                "data_208:",
+                "DB 0xAA",
+            ]
+        );
+    }
+
+    #[test]
+    fn octo_listings_start_at_main() {
+        let rom = [
+            0x22, 0x06, // sub_206
+            0x12, 0x00, // jump main
+            0xA2, 0x0A, // not reachable
+            0x63, 0x12, // v3 := 0x12
+            0xD0, 0x15, // sprite v0 v1 5
+            0x00, 0xEE, // return
+        ];
+        assert_eq!(
+            lines(&rom, Syntax::Octo),
+            [
+                ": main",
+                "sub_206",
+                "jump main",
+                "0xA2 0x0A",
+                ": sub_206",
+                "v3 := 0x12",
+                "sprite v0 v1 5",
+                "return",
+            ]
+        );
+    }
+
+    #[test]
+    fn classic_listings_have_no_main_label() {
+        assert_eq!(lines(&[0x00, 0xE0], Syntax::Classic), ["CLS"]);
+        assert_eq!(lines(&[0x00, 0xE0], Syntax::Octo), [": main", "clear"]);
+    }
+}
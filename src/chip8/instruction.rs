@@ -2,7 +2,7 @@ use log::*;
 use std::fmt;
 
 use super::opcode::{OpCode, Operands};
-use super::Chip8;
+use super::{Chip8, STACK_SIZE};
 
 pub type InsFn = fn(&mut Chip8, Operands) -> bool;
 pub type InsName = &'static str;
@@ -36,16 +36,14 @@ impl Instruction {
 // ------------ //
 
 pub fn not_implemented(e: &mut Chip8, _: Operands) -> bool {
-    let instruction = e.get_opcode(e.reg_pc - 2).decode();
+    let instruction = e.get_opcode(e.reg_pc.wrapping_sub(2)).decode(e.platform);
     warn!("Ignoring unimplemented instruction: {}", instruction);
     false
 }
 
 pub fn cls_00e0(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Empty = o {
-        for mut px in e.screen.iter_mut() {
-            *px = false;
-        }
+        clear_planes(e);
     }
     true
 }
@@ -66,6 +64,15 @@ pub fn jp_1nnn(e: &mut Chip8, o: Operands) -> bool {
 
 pub fn call_2nnn(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Address(a) = o {
+        // Keep the most recent return addresses when the program recurses too deep
+        if e.stack.len() == STACK_SIZE {
+            let addr = e.reg_pc.wrapping_sub(2);
+            warn!(
+                "Stack overflow at {:#06X}, dropping the oldest return address",
+                addr
+            );
+            e.stack.remove(0);
+        }
         e.stack.push(e.reg_pc);
         e.reg_pc = a;
     }
@@ -75,7 +82,7 @@ pub fn call_2nnn(e: &mut Chip8, o: Operands) -> bool {
 pub fn se_3xnn(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::RegAndConst(r, c) = o {
         if e.regs[r] == c {
-            e.pc_inc();
+            e.skip();
         }
     }
     false
@@ -84,7 +91,7 @@ pub fn se_3xnn(e: &mut Chip8, o: Operands) -> bool {
 pub fn sne_4xnn(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::RegAndConst(r, c) = o {
         if e.regs[r] != c {
-            e.pc_inc();
+            e.skip();
         }
     }
     false
@@ -93,7 +100,7 @@ pub fn sne_4xnn(e: &mut Chip8, o: Operands) -> bool {
 pub fn se_5xy0(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Regs(x, y) = o {
         if e.regs[x] == e.regs[y] {
-            e.pc_inc();
+            e.skip();
         }
     }
     false
@@ -125,6 +132,9 @@ pub fn ld_8xy0(e: &mut Chip8, o: Operands) -> bool {
 pub fn or_8xy1(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Regs(x, y) = o {
         e.regs[x] |= e.regs[y];
+        if e.quirks.logic_reset_vf {
+            e.clear_flag();
+        }
     }
     false
 }
@@ -132,6 +142,9 @@ pub fn or_8xy1(e: &mut Chip8, o: Operands) -> bool {
 pub fn and_8xy2(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Regs(x, y) = o {
         e.regs[x] &= e.regs[y];
+        if e.quirks.logic_reset_vf {
+            e.clear_flag();
+        }
     }
     false
 }
@@ -139,6 +152,9 @@ pub fn and_8xy2(e: &mut Chip8, o: Operands) -> bool {
 pub fn xor_8xy3(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Regs(x, y) = o {
         e.regs[x] ^= e.regs[y];
+        if e.quirks.logic_reset_vf {
+            e.clear_flag();
+        }
     }
     false
 }
@@ -146,12 +162,13 @@ pub fn xor_8xy3(e: &mut Chip8, o: Operands) -> bool {
 pub fn add_8xy4(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Regs(x, y) = o {
         let res = (e.regs[x] as u16) + (e.regs[y] as u16);
+        // The flag is written last, so it wins when VF is the destination
+        e.regs[x] = (res & 0x00FF) as u8;
         if res > 255 {
             e.set_flag()
         } else {
             e.clear_flag()
         }
-        e.regs[x] = (res & 0x00FF) as u8;
     }
     false
 }
@@ -160,12 +177,12 @@ pub fn sub_8xy5(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Regs(x, y) = o {
         let lhs = e.regs[x];
         let rhs = e.regs[y];
-        if  lhs > rhs {
+        // VF is set when there is no borrow, it is written last like in `add_8xy4`
+        e.regs[x] = lhs.wrapping_sub(rhs);
+        if lhs >= rhs {
             e.set_flag();
-            e.regs[x] = lhs - rhs;
         } else {
             e.clear_flag();
-            e.regs[x] = 0xFF - (rhs - lhs);
         }
     }
     false
@@ -173,11 +190,10 @@ pub fn sub_8xy5(e: &mut Chip8, o: Operands) -> bool {
 
 
 pub fn shr_8xy6(e: &mut Chip8, o: Operands) -> bool {
-    if let Operands::Regs(x, _) = o {
-        // Super Chip8 behaviour
-        let val = e.regs[x];
-        e.regs[0xF] = val & 0x01;
+    if let Operands::Regs(x, y) = o {
+        let val = if e.quirks.shift_vy { e.regs[y] } else { e.regs[x] };
         e.regs[x] = val >> 1;
+        e.regs[0xF] = val & 0x01;
     }
     false
 }
@@ -186,23 +202,21 @@ pub fn sub_8xy7(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Regs(x, y) = o {
         let lhs = e.regs[x];
         let rhs = e.regs[y];
-        if  rhs > lhs {
+        e.regs[x] = rhs.wrapping_sub(lhs);
+        if rhs >= lhs {
             e.set_flag();
-            e.regs[x] = rhs - lhs;
         } else {
             e.clear_flag();
-            e.regs[x] = 0xFF - (lhs - rhs);
         }
     }
     false
 }
 
 pub fn shl_8xye(e: &mut Chip8, o: Operands) -> bool {
-    if let Operands::Regs(x, _) = o {
-        // Super Chip8 behaviour
-        let val = e.regs[x];
-        e.regs[0xF] = (val & 0b10000000) >> 7;
+    if let Operands::Regs(x, y) = o {
+        let val = if e.quirks.shift_vy { e.regs[y] } else { e.regs[x] };
         e.regs[x] = val << 1;
+        e.regs[0xF] = (val & 0b10000000) >> 7;
     }
     false
 }
@@ -210,7 +224,7 @@ pub fn shl_8xye(e: &mut Chip8, o: Operands) -> bool {
 pub fn sne_9xy0(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Regs(x, y) = o {
         if e.regs[x] != e.regs[y] {
-            e.pc_inc();
+            e.skip();
         }
     }
     false
@@ -225,7 +239,8 @@ pub fn ld_annn(e: &mut Chip8, o: Operands) -> bool {
 
 pub fn jp_bnnn(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Address(a) = o {
-        e.reg_pc = a + (e.regs[0] as u16);
+        let r = if e.quirks.jump_vx { (a >> 8) as u8 } else { 0 };
+        e.reg_pc = a + (e.regs[r] as u16);
     }
     false
 }
@@ -233,60 +248,140 @@ pub fn jp_bnnn(e: &mut Chip8, o: Operands) -> bool {
 
 pub fn rnd_cxkk(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::RegAndConst(r, c) = o {
-        let rand: u8 = rand::random();
-        e.regs[r] = rand & c;
+        e.regs[r] = e.random() & c;
     }
     false
 }
 
 pub fn drw_dxyn(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::RegsAndConst(x, y, c) = o {
-        // Reset flag register
-        e.clear_flag();
+        // Super Chip8 draws a 16x16 sprite when n is 0
+        let (width, height) = if c == 0 && e.platform.superchip() {
+            (16, 16)
+        } else {
+            (8, c as usize)
+        };
 
+        // Feth the location to draw, the starting position always wraps around
+        let (buf_width, buf_height) = e.platform.screen_size();
+        let scale = e.pixel_scale();
+        let base_x = e.regs[x] as usize % (buf_width / scale);
+        let base_y = e.regs[y] as usize % (buf_height / scale);
+
+        // XO-Chip stores the sprite of each selected plane after each other
+        let mut addr = e.reg_i;
         let mut collision = false;
 
-        // Fetch the sprite
-        let i = e.reg_i as usize;
-        let sprite = &e.mem.0[i..i + c as usize];
+        for plane in [0b01, 0b10].iter() {
+            if e.planes & plane != 0 {
+                collision |= draw_sprite(e, addr, *plane, base_x, base_y, width, height);
+                addr = addr.wrapping_add((width / 8 * height) as u16);
+            }
+        }
+
+        if collision {
+            e.set_flag();
+        } else {
+            e.clear_flag();
+        }
 
-        // Feth the location to draw
-        let base_x = e.regs[x];
-        let base_y = e.regs[y];
+        e.vblank_wait = e.quirks.display_wait;
+    }
+    true
+}
 
-        // Iterate over every bit in the sprite, to chec if it is set
-        for (sprite_y, y) in (base_y..(base_y + c)).enumerate() {
-            for (sprite_x, x) in (base_x..(base_x + 8)).enumerate() {
-                if (sprite[sprite_y] & (0b10000000 >> sprite_x)) != 0 {
-                    // Coordinates should wrap around
-                    let x = x as usize % super::WIDTH;
-                    let y = y as usize % super::HEIGHT;
-                    let addr =  y * super::WIDTH + x ;
+/// XOR the sprite at `addr` onto `plane`, returns true if a lit pixel was erased.
+fn draw_sprite(
+    e: &mut Chip8,
+    addr: u16,
+    plane: u8,
+    base_x: usize,
+    base_y: usize,
+    width: usize,
+    height: usize,
+) -> bool {
+    let (buf_width, buf_height) = e.platform.screen_size();
+    let scale = e.pixel_scale();
+    let row_bytes = width / 8;
+
+    let mut collision = false;
+
+    // Iterate over every bit in the sprite, to check if it is set
+    for sprite_y in 0..height {
+        let mut byte = 0;
+        for sprite_x in 0..width {
+            if sprite_x % 8 == 0 {
+                byte = e.read(addr.wrapping_add((sprite_y * row_bytes + sprite_x / 8) as u16));
+            }
+            if byte & (0b10000000 >> (sprite_x % 8)) == 0 {
+                continue;
+            }
+
+            // The sprite is either clipped or wraps around at the edge of the screen
+            let (x, y) = (base_x + sprite_x, base_y + sprite_y);
+            if e.quirks.clip && (x >= buf_width / scale || y >= buf_height / scale) {
+                continue;
+            }
+            let x = x % (buf_width / scale);
+            let y = y % (buf_height / scale);
+
+            // In low resolution mode, a pixel covers several pixels of the screen buffer
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let addr = (y * scale + dy) * buf_width + x * scale + dx;
 
                     // Collision check
-                    if e.screen[addr] {
+                    if e.screen[addr] & plane != 0 {
                         collision = true;
                     }
 
                     // Update the display
-                    let cur = e.screen[addr];
-                    e.screen.set(addr, cur ^ true);
+                    e.screen[addr] ^= plane;
                 }
             }
         }
+    }
 
-        // Need to do this out of the loop to make the borrow checker happy
-        if collision {
-            e.set_flag();
+    collision
+}
+
+/// Clear the selected planes of the screen.
+fn clear_planes(e: &mut Chip8) {
+    let planes = e.planes;
+    for px in e.screen.iter_mut() {
+        *px &= !planes;
+    }
+}
+
+/// Move the selected planes of the screen by (`dx`, `dy`) buffer pixels.
+fn scroll(e: &mut Chip8, dx: isize, dy: isize) {
+    let (width, height) = e.platform.screen_size();
+    let planes = e.planes;
+    let old = e.screen.clone();
+
+    for y in 0..height {
+        for x in 0..width {
+            let src_x = x as isize - dx;
+            let src_y = y as isize - dy;
+
+            let inside =
+                (0..width as isize).contains(&src_x) && (0..height as isize).contains(&src_y);
+            let src = if inside {
+                old[src_y as usize * width + src_x as usize] & planes
+            } else {
+                0
+            };
+
+            let addr = y * width + x;
+            e.screen[addr] = (e.screen[addr] & !planes) | src;
         }
     }
-    true
 }
 
 pub fn skp_ex9e(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Reg(r) = o {
         if e.keypad[(e.regs[r] & 0x0F) as usize] {
-            e.pc_inc();
+            e.skip();
         }
     }
     false
@@ -295,7 +390,7 @@ pub fn skp_ex9e(e: &mut Chip8, o: Operands) -> bool {
 pub fn sknp_exa1(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Reg(r) = o {
         if !e.keypad[(e.regs[r] & 0xF) as usize] {
-            e.pc_inc();
+            e.skip();
         }
     }
     false
@@ -331,7 +426,7 @@ pub fn ld_fx18(e: &mut Chip8, o: Operands) -> bool {
 
 pub fn add_fx1e(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Reg(r) = o {
-        e.reg_i += e.regs[r] as u16;
+        e.reg_i = e.reg_i.wrapping_add(e.regs[r] as u16);
     }
     false
 }
@@ -340,9 +435,9 @@ pub fn ld_fx33(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Reg(r) = o {
         let num = e.regs[r];
 
-        e.mem[e.reg_i + 0] = num / 100;
-        e.mem[e.reg_i + 1] = (num % 100) / 10;
-        e.mem[e.reg_i + 2] = num % 10;
+        e.write(e.reg_i, num / 100);
+        e.write(e.reg_i.wrapping_add(1), (num % 100) / 10);
+        e.write(e.reg_i.wrapping_add(2), num % 10);
 
     }
     false
@@ -350,9 +445,11 @@ pub fn ld_fx33(e: &mut Chip8, o: Operands) -> bool {
 
 pub fn ld_fx55(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Reg(r) = o {
-        // Super Chip8 behaviour
         for ctr in 0..(r + 1) {
-            e.mem[e.reg_i + ctr as u16] = e.regs[ctr];
+            e.write(e.reg_i.wrapping_add(ctr as u16), e.regs[ctr]);
+        }
+        if e.quirks.increment_i {
+            e.reg_i = e.reg_i.wrapping_add(r as u16 + 1);
         }
     }
     false
@@ -360,9 +457,11 @@ pub fn ld_fx55(e: &mut Chip8, o: Operands) -> bool {
 
 pub fn ld_fx65(e: &mut Chip8, o: Operands) -> bool {
     if let Operands::Reg(r) = o {
-        // Super Chip8 behaviour
         for ctr in 0..(r + 1) {
-            e.regs[ctr] = e.mem[e.reg_i + ctr as u16];
+            e.regs[ctr] = e.read(e.reg_i.wrapping_add(ctr as u16));
+        }
+        if e.quirks.increment_i {
+            e.reg_i = e.reg_i.wrapping_add(r as u16 + 1);
         }
     }
     false
@@ -376,6 +475,163 @@ pub fn ld_fx29(e: &mut Chip8, o: Operands) -> bool {
     false
 }
 
+// ------------------------ //
+// Super Chip8 Instructions //
+// ------------------------ //
+
+pub fn scd_00cn(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Const(n) = o {
+        let dy = n as usize * e.scroll_scale();
+        scroll(e, 0, dy as isize);
+    }
+    true
+}
+
+pub fn scr_00fb(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Empty = o {
+        let dx = 4 * e.scroll_scale();
+        scroll(e, dx as isize, 0);
+    }
+    true
+}
+
+pub fn scl_00fc(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Empty = o {
+        let dx = 4 * e.scroll_scale();
+        scroll(e, -(dx as isize), 0);
+    }
+    true
+}
+
+pub fn exit_00fd(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Empty = o {
+        info!("Program exited");
+        e.halted = true;
+    }
+    false
+}
+
+pub fn low_00fe(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Empty = o {
+        e.hires = false;
+        // XO-Chip clears the screen when switching resolutions
+        if e.platform.xochip() {
+            e.screen.fill(0);
+        }
+    }
+    e.platform.xochip()
+}
+
+pub fn high_00ff(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Empty = o {
+        e.hires = true;
+        if e.platform.xochip() {
+            e.screen.fill(0);
+        }
+    }
+    e.platform.xochip()
+}
+
+pub fn ld_fx30(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Reg(r) = o {
+        let addr = e.regs[r] & 0x0F;
+        e.reg_i = e.large_sprite_addr(addr);
+    }
+    false
+}
+
+pub fn ld_fx75(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Reg(r) = o {
+        // Super Chip8 can only store V0 to V7 in the RPL flags
+        let last = r.min(e.platform.rpl_amount() as u8 - 1);
+        for ctr in 0..=last {
+            e.rpl[ctr as usize] = e.regs[ctr];
+        }
+    }
+    false
+}
+
+pub fn ld_fx85(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Reg(r) = o {
+        let last = r.min(e.platform.rpl_amount() as u8 - 1);
+        for ctr in 0..=last {
+            e.regs[ctr] = e.rpl[ctr as usize];
+        }
+    }
+    false
+}
+
+// -------------------- //
+// XO-Chip Instructions //
+// -------------------- //
+
+pub fn scu_00dn(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Const(n) = o {
+        let dy = n as usize * e.scroll_scale();
+        scroll(e, 0, -(dy as isize));
+    }
+    true
+}
+
+pub fn save_5xy2(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Regs(x, y) = o {
+        // Registers are stored in reverse order if x > y, I is not modified
+        for (ctr, r) in reg_range(x, y).enumerate() {
+            e.write(e.reg_i.wrapping_add(ctr as u16), e.regs[r]);
+        }
+    }
+    false
+}
+
+pub fn load_5xy3(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Regs(x, y) = o {
+        for (ctr, r) in reg_range(x, y).enumerate() {
+            e.regs[r] = e.read(e.reg_i.wrapping_add(ctr as u16));
+        }
+    }
+    false
+}
+
+fn reg_range(x: u8, y: u8) -> Box<dyn Iterator<Item = u8>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+pub fn long_f000(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Empty = o {
+        // The address is stored in the next 2 bytes
+        e.reg_i = e.get_word(e.reg_pc);
+        e.pc_inc();
+    }
+    false
+}
+
+pub fn plane_fn01(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Const(n) = o {
+        e.planes = n & 0b11;
+    }
+    false
+}
+
+pub fn audio_f002(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Empty = o {
+        for ctr in 0..e.pattern.len() {
+            e.pattern[ctr] = e.read(e.reg_i.wrapping_add(ctr as u16));
+        }
+    }
+    false
+}
+
+pub fn pitch_fx3a(e: &mut Chip8, o: Operands) -> bool {
+    if let Operands::Reg(r) = o {
+        e.pitch = e.regs[r];
+    }
+    false
+}
+
 // ------------------ //
 // Formatting Support //
 // ------------------ //
@@ -389,3 +645,723 @@ impl fmt::Display for Instruction {
         )
     }
 }
+
+// ----- //
+// Tests //
+// ----- //
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Chip8, Platform, Quirks};
+    use crate::input::Input;
+    use crate::Emulator;
+
+    fn chip8() -> Chip8 {
+        Chip8::new(Platform::Chip8, Quirks::vip())
+    }
+
+    fn with_quirks(quirks: Quirks) -> Chip8 {
+        Chip8::new(Platform::Chip8, quirks)
+    }
+
+    // Execute a single instruction at the program counter
+    fn exec(e: &mut Chip8, code: u16) -> bool {
+        let pc = e.reg_pc;
+        e.mem[pc] = (code >> 8) as u8;
+        e.mem[pc.wrapping_add(1)] = code as u8;
+        e.step()
+    }
+
+    // Flow control
+    // ------------
+
+    #[test]
+    fn cls_00e0_clears_the_screen() {
+        let mut e = chip8();
+        e.screen[0] = 1;
+        assert!(exec(&mut e, 0x00E0));
+        assert!(e.screen.iter().all(|px| *px == 0));
+    }
+
+    #[test]
+    fn call_2nnn_and_ret_00ee() {
+        let mut e = chip8();
+        exec(&mut e, 0x2300);
+        assert_eq!(e.reg_pc, 0x300);
+        assert_eq!(e.stack, vec![0x202]);
+
+        exec(&mut e, 0x00EE);
+        assert_eq!(e.reg_pc, 0x202);
+        assert!(e.stack.is_empty());
+    }
+
+    #[test]
+    fn call_2nnn_drops_the_oldest_return_address_on_overflow() {
+        let mut e = chip8();
+        // Each call jumps to the next instruction, returning to the one after it
+        for _ in 0..20 {
+            let next = e.reg_pc + 2;
+            exec(&mut e, 0x2000 | next);
+        }
+        assert_eq!(e.stack.len(), 16);
+        assert_eq!(e.stack[0], 0x202 + 2 * 4);
+        assert_eq!(e.stack[15], 0x202 + 2 * 19);
+    }
+
+    #[test]
+    fn unimplemented_instruction_at_the_end_of_memory_is_skipped() {
+        let mut e = Chip8::new(Platform::XoChip, Quirks::xochip());
+        e.reg_pc = 0xFFFE;
+        exec(&mut e, 0x0123);
+        assert_eq!(e.reg_pc, 0x0000);
+    }
+
+    #[test]
+    fn jp_1nnn() {
+        let mut e = chip8();
+        exec(&mut e, 0x1ABC);
+        assert_eq!(e.reg_pc, 0xABC);
+    }
+
+    #[test]
+    fn jp_bnnn_adds_v0() {
+        let mut e = chip8();
+        e.regs[0] = 4;
+        e.regs[3] = 8;
+        exec(&mut e, 0xB310);
+        assert_eq!(e.reg_pc, 0x314);
+    }
+
+    #[test]
+    fn jp_bnnn_adds_vx_with_jump_vx_quirk() {
+        let mut e = with_quirks(Quirks::chip48());
+        e.regs[0] = 4;
+        e.regs[3] = 8;
+        exec(&mut e, 0xB310);
+        assert_eq!(e.reg_pc, 0x318);
+    }
+
+    // Skips
+    // -----
+
+    #[test]
+    fn se_3xnn() {
+        let mut e = chip8();
+        e.regs[1] = 0x12;
+        exec(&mut e, 0x3112);
+        assert_eq!(e.reg_pc, 0x204);
+        exec(&mut e, 0x3113);
+        assert_eq!(e.reg_pc, 0x206);
+    }
+
+    #[test]
+    fn sne_4xnn() {
+        let mut e = chip8();
+        e.regs[1] = 0x12;
+        exec(&mut e, 0x4112);
+        assert_eq!(e.reg_pc, 0x202);
+        exec(&mut e, 0x4113);
+        assert_eq!(e.reg_pc, 0x206);
+    }
+
+    #[test]
+    fn se_5xy0() {
+        let mut e = chip8();
+        e.regs[1] = 7;
+        e.regs[2] = 7;
+        exec(&mut e, 0x5120);
+        assert_eq!(e.reg_pc, 0x204);
+        e.regs[2] = 8;
+        exec(&mut e, 0x5120);
+        assert_eq!(e.reg_pc, 0x206);
+    }
+
+    #[test]
+    fn sne_9xy0() {
+        let mut e = chip8();
+        e.regs[1] = 7;
+        e.regs[2] = 7;
+        exec(&mut e, 0x9120);
+        assert_eq!(e.reg_pc, 0x202);
+        e.regs[2] = 8;
+        exec(&mut e, 0x9120);
+        assert_eq!(e.reg_pc, 0x206);
+    }
+
+    #[test]
+    fn skips_step_over_long_on_xochip() {
+        let mut e = Chip8::new(Platform::XoChip, Quirks::xochip());
+        e.mem.0[0x202..0x206].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+        exec(&mut e, 0x3000);
+        assert_eq!(e.reg_pc, 0x206);
+    }
+
+    #[test]
+    fn skp_ex9e_and_sknp_exa1() {
+        let mut e = chip8();
+        e.regs[0] = 5;
+        exec(&mut e, 0xE09E);
+        assert_eq!(e.reg_pc, 0x202);
+        exec(&mut e, 0xE0A1);
+        assert_eq!(e.reg_pc, 0x206);
+
+        e.key_down(Input::Keypad(5));
+        exec(&mut e, 0xE09E);
+        assert_eq!(e.reg_pc, 0x20A);
+        exec(&mut e, 0xE0A1);
+        assert_eq!(e.reg_pc, 0x20C);
+    }
+
+    // Registers
+    // ---------
+
+    #[test]
+    fn ld_6xkk_and_ld_8xy0() {
+        let mut e = chip8();
+        exec(&mut e, 0x61AB);
+        exec(&mut e, 0x8010);
+        assert_eq!(e.regs[0], 0xAB);
+        assert_eq!(e.regs[1], 0xAB);
+    }
+
+    #[test]
+    fn add_7xkk_wraps_without_flag() {
+        let mut e = chip8();
+        e.regs[0] = 0xFF;
+        e.regs[0xF] = 5;
+        exec(&mut e, 0x7002);
+        assert_eq!(e.regs[0], 0x01);
+        assert_eq!(e.regs[0xF], 5);
+    }
+
+    #[test]
+    fn logic_resets_vf_with_quirk() {
+        for (code, res) in [(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)].iter() {
+            let mut e = chip8();
+            e.regs[0] = 0b1100;
+            e.regs[1] = 0b1010;
+            e.regs[0xF] = 5;
+            exec(&mut e, *code);
+            assert_eq!(e.regs[0], *res);
+            assert_eq!(e.regs[0xF], 0);
+        }
+    }
+
+    #[test]
+    fn logic_keeps_vf_without_quirk() {
+        for code in [0x8011, 0x8012, 0x8013].iter() {
+            let mut e = with_quirks(Quirks::chip48());
+            e.regs[0xF] = 5;
+            exec(&mut e, *code);
+            assert_eq!(e.regs[0xF], 5);
+        }
+    }
+
+    #[test]
+    fn add_8xy4_sets_carry() {
+        let mut e = chip8();
+        e.regs[0] = 0xFF;
+        e.regs[1] = 0x02;
+        exec(&mut e, 0x8014);
+        assert_eq!(e.regs[0], 0x01);
+        assert_eq!(e.regs[0xF], 1);
+
+        exec(&mut e, 0x8014);
+        assert_eq!(e.regs[0], 0x03);
+        assert_eq!(e.regs[0xF], 0);
+    }
+
+    #[test]
+    fn add_8xy4_flag_wins_in_vf() {
+        let mut e = chip8();
+        e.regs[0xF] = 0xFF;
+        e.regs[1] = 0x02;
+        exec(&mut e, 0x8F14);
+        assert_eq!(e.regs[0xF], 1);
+    }
+
+    #[test]
+    fn sub_8xy5_equal_operands_do_not_borrow() {
+        let mut e = chip8();
+        e.regs[0] = 5;
+        e.regs[1] = 5;
+        exec(&mut e, 0x8015);
+        assert_eq!(e.regs[0], 0);
+        assert_eq!(e.regs[0xF], 1);
+    }
+
+    #[test]
+    fn sub_8xy5_wraps_on_borrow() {
+        let mut e = chip8();
+        e.regs[0] = 1;
+        e.regs[1] = 2;
+        exec(&mut e, 0x8015);
+        assert_eq!(e.regs[0], 0xFF);
+        assert_eq!(e.regs[0xF], 0);
+    }
+
+    #[test]
+    fn sub_8xy5_flag_wins_in_vf() {
+        let mut e = chip8();
+        e.regs[0xF] = 5;
+        e.regs[1] = 3;
+        exec(&mut e, 0x8F15);
+        assert_eq!(e.regs[0xF], 1);
+    }
+
+    #[test]
+    fn sub_8xy7_equal_operands_do_not_borrow() {
+        let mut e = chip8();
+        e.regs[0] = 5;
+        e.regs[1] = 5;
+        exec(&mut e, 0x8017);
+        assert_eq!(e.regs[0], 0);
+        assert_eq!(e.regs[0xF], 1);
+    }
+
+    #[test]
+    fn sub_8xy7_wraps_on_borrow() {
+        let mut e = chip8();
+        e.regs[0] = 2;
+        e.regs[1] = 1;
+        exec(&mut e, 0x8017);
+        assert_eq!(e.regs[0], 0xFF);
+        assert_eq!(e.regs[0xF], 0);
+    }
+
+    #[test]
+    fn sub_8xy7_flag_wins_in_vf() {
+        let mut e = chip8();
+        e.regs[0xF] = 3;
+        e.regs[1] = 5;
+        exec(&mut e, 0x8F17);
+        assert_eq!(e.regs[0xF], 1);
+    }
+
+    #[test]
+    fn shr_8xy6_shifts_vy_with_quirk() {
+        let mut e = chip8();
+        e.regs[0] = 0xF0;
+        e.regs[1] = 0b11;
+        exec(&mut e, 0x8016);
+        assert_eq!(e.regs[0], 0b1);
+        assert_eq!(e.regs[0xF], 1);
+    }
+
+    #[test]
+    fn shr_8xy6_shifts_vx_without_quirk() {
+        let mut e = with_quirks(Quirks::chip48());
+        e.regs[0] = 0b10;
+        e.regs[1] = 0b11;
+        exec(&mut e, 0x8016);
+        assert_eq!(e.regs[0], 0b1);
+        assert_eq!(e.regs[0xF], 0);
+    }
+
+    #[test]
+    fn shl_8xye_sets_shifted_out_bit() {
+        let mut e = with_quirks(Quirks::chip48());
+        e.regs[0] = 0x81;
+        exec(&mut e, 0x801E);
+        assert_eq!(e.regs[0], 0x02);
+        assert_eq!(e.regs[0xF], 1);
+    }
+
+    #[test]
+    fn shifts_flag_wins_in_vf() {
+        let mut e = chip8();
+        e.regs[0xF] = 0b10;
+        exec(&mut e, 0x8FF6);
+        assert_eq!(e.regs[0xF], 0);
+
+        e.regs[0xF] = 0x81;
+        exec(&mut e, 0x8FFE);
+        assert_eq!(e.regs[0xF], 1);
+    }
+
+    #[test]
+    fn rnd_cxkk_is_masked_and_seeded() {
+        let mut e = chip8();
+        let mut f = chip8();
+        e.seed(1);
+        f.seed(1);
+
+        for _ in 0..32 {
+            exec(&mut e, 0xC00F);
+            exec(&mut f, 0xC00F);
+            assert_eq!(e.regs[0] & 0xF0, 0);
+            assert_eq!(e.regs[0], f.regs[0]);
+        }
+        exec(&mut e, 0xC000);
+        assert_eq!(e.regs[0], 0);
+    }
+
+    #[test]
+    fn rnd_cxkk_follows_the_first_page_in_vip_mode() {
+        let mut e = chip8();
+        let mut f = chip8();
+        e.set_vip_random(true);
+        f.set_vip_random(true);
+        e.seed(1);
+        f.seed(1);
+        f.mem[0x05] ^= 0xFF;
+
+        let mut numbers = Vec::new();
+        for _ in 0..8 {
+            exec(&mut e, 0xC0FF);
+            exec(&mut f, 0xC0FF);
+            numbers.push((e.regs[0], f.regs[0]));
+        }
+        // The pointer starts after the seed, so the first different byte is read on the 4th call
+        assert!(numbers[..3].iter().all(|(a, b)| a == b));
+        assert_ne!(numbers[3].0, numbers[3].1);
+
+        exec(&mut e, 0xC00F);
+        assert_eq!(e.regs[0] & 0xF0, 0);
+    }
+
+    // Memory
+    // ------
+
+    #[test]
+    fn ld_annn() {
+        let mut e = chip8();
+        exec(&mut e, 0xA123);
+        assert_eq!(e.reg_i, 0x123);
+    }
+
+    #[test]
+    fn add_fx1e_wraps() {
+        let mut e = chip8();
+        e.reg_i = 0xFFFF;
+        e.regs[0] = 2;
+        exec(&mut e, 0xF01E);
+        assert_eq!(e.reg_i, 0x0001);
+    }
+
+    #[test]
+    fn ld_fx29_points_at_font() {
+        let mut e = chip8();
+        e.regs[0] = 0x1A;
+        exec(&mut e, 0xF029);
+        assert_eq!(e.reg_i, 0xA * 5);
+    }
+
+    #[test]
+    fn ld_fx33_stores_bcd() {
+        let mut e = chip8();
+        e.reg_i = 0x300;
+        e.regs[0] = 123;
+        exec(&mut e, 0xF033);
+        assert_eq!(&e.mem.0[0x300..0x303], &[1, 2, 3]);
+        assert_eq!(e.reg_i, 0x300);
+    }
+
+    #[test]
+    fn ld_fx55_and_fx65_increment_i_with_quirk() {
+        let mut e = chip8();
+        e.reg_i = 0x300;
+        e.regs[0] = 1;
+        e.regs[1] = 2;
+        exec(&mut e, 0xF155);
+        assert_eq!(&e.mem.0[0x300..0x302], &[1, 2]);
+        assert_eq!(e.reg_i, 0x302);
+
+        e.reg_i = 0x300;
+        e.regs[0] = 0;
+        e.regs[1] = 0;
+        exec(&mut e, 0xF165);
+        assert_eq!(&e.regs.0[0..2], &[1, 2]);
+        assert_eq!(e.reg_i, 0x302);
+    }
+
+    #[test]
+    fn ld_fx55_and_fx65_keep_i_without_quirk() {
+        let mut e = with_quirks(Quirks::chip48());
+        e.reg_i = 0x300;
+        exec(&mut e, 0xF155);
+        assert_eq!(e.reg_i, 0x300);
+        exec(&mut e, 0xF165);
+        assert_eq!(e.reg_i, 0x300);
+    }
+
+    #[test]
+    fn ld_fx55_wraps_around_memory() {
+        let mut e = chip8();
+        e.reg_i = 0xFFF;
+        e.regs[0] = 1;
+        e.regs[1] = 2;
+        exec(&mut e, 0xF155);
+        assert_eq!(e.mem.0[0xFFF], 1);
+        assert_eq!(e.mem.0[0x000], 2);
+    }
+
+    // Timers and keys
+    // ---------------
+
+    #[test]
+    fn timers_fx07_fx15_fx18() {
+        let mut e = chip8();
+        e.regs[0] = 30;
+        exec(&mut e, 0xF015);
+        exec(&mut e, 0xF018);
+        exec(&mut e, 0xF107);
+        assert_eq!(e.reg_dt, 30);
+        assert_eq!(e.reg_st, 30);
+        assert_eq!(e.regs[1], 30);
+    }
+
+    #[test]
+    fn ld_fx0a_waits_for_a_key() {
+        let mut e = chip8();
+        exec(&mut e, 0xF30A);
+        assert_eq!(e.await_press, Some(3));
+        assert!(!e.cycle());
+        assert_eq!(e.reg_pc, 0x202);
+
+        e.key_down(Input::Keypad(0xB));
+        assert_eq!(e.await_press, None);
+        assert_eq!(e.regs[3], 0xB);
+    }
+
+    #[test]
+    fn timers_tick_once_per_locked_frame() {
+        let mut e = chip8();
+        e.set_frame_locked(true);
+        e.set_cycles_per_frame(10);
+        // Jump onto itself forever
+        e.mem[0x200] = 0x12;
+        e.mem[0x201] = 0x00;
+        e.reg_dt = 5;
+
+        e.run(30);
+        assert_eq!(e.reg_dt, 2);
+        e.run(9);
+        assert_eq!(e.reg_dt, 2);
+        e.run(1);
+        assert_eq!(e.reg_dt, 1);
+    }
+
+    // Drawing
+    // -------
+
+    #[test]
+    fn drw_dxyn_sets_collision_flag() {
+        let mut e = chip8();
+        e.reg_i = e.sprite_addr(0);
+        assert!(exec(&mut e, 0xD015));
+        assert!(e.pixel(0, 0));
+        assert!(!e.pixel(1, 1));
+        assert_eq!(e.regs[0xF], 0);
+
+        exec(&mut e, 0xD015);
+        assert!(e.screen.iter().all(|px| *px == 0));
+        assert_eq!(e.regs[0xF], 1);
+    }
+
+    #[test]
+    fn drw_dxyn_waits_for_display_with_quirk() {
+        let mut e = chip8();
+        e.reg_i = e.sprite_addr(0);
+        exec(&mut e, 0xD015);
+        e.mem[0x202] = 0x60;
+        e.mem[0x203] = 0x05;
+
+        assert!(!e.cycle());
+        assert_eq!(e.reg_pc, 0x202);
+
+        e.tick();
+        e.cycle();
+        assert_eq!(e.reg_pc, 0x204);
+    }
+
+    #[test]
+    fn drw_dxyn_does_not_wait_without_quirk() {
+        let mut e = with_quirks(Quirks::schip());
+        e.reg_i = e.sprite_addr(0);
+        exec(&mut e, 0xD015);
+        e.mem[0x202] = 0x60;
+        e.mem[0x203] = 0x05;
+
+        e.cycle();
+        assert_eq!(e.reg_pc, 0x204);
+    }
+
+    #[test]
+    fn drw_dxyn_draws_once_per_frame_with_display_wait() {
+        // Count the draw in V1, draw and start over
+        let program = [0x71, 0x01, 0xD0, 0x15, 0x12, 0x00];
+
+        for (quirks, draws) in [(Quirks::vip(), 5), (Quirks::schip(), 17)] {
+            let mut e = with_quirks(quirks);
+            e.load_rom(program.to_vec());
+            e.reg_i = e.sprite_addr(0);
+            e.set_cycles_per_frame(10);
+            e.set_frame_locked(true);
+
+            e.run(50);
+            assert_eq!(e.regs[1], draws);
+        }
+    }
+
+    #[test]
+    fn drw_dxyn_wraps_start_position() {
+        let mut e = chip8();
+        e.reg_i = e.sprite_addr(0);
+        e.regs[0] = 64 + 2;
+        e.regs[1] = 32 + 1;
+        exec(&mut e, 0xD015);
+        assert!(e.pixel(2, 1));
+    }
+
+    #[test]
+    fn drw_dxyn_clips_with_quirk() {
+        let mut e = chip8();
+        e.reg_i = e.sprite_addr(0);
+        e.regs[0] = 62;
+        exec(&mut e, 0xD015);
+        assert!(e.pixel(63, 0));
+        assert!(!e.pixel(0, 0));
+    }
+
+    #[test]
+    fn drw_dxyn_wraps_without_quirk() {
+        let mut e = with_quirks(Quirks::xochip());
+        e.reg_i = e.sprite_addr(0);
+        e.regs[0] = 62;
+        exec(&mut e, 0xD015);
+        assert!(e.pixel(63, 0));
+        assert!(e.pixel(0, 0));
+    }
+
+    // Super Chip8
+    // -----------
+
+    fn schip() -> Chip8 {
+        Chip8::new(Platform::SuperChip, Quirks::schip())
+    }
+
+    #[test]
+    fn exit_00fd_halts() {
+        let mut e = schip();
+        exec(&mut e, 0x00FD);
+        assert!(e.halted);
+        assert!(!e.cycle());
+    }
+
+    #[test]
+    fn high_00ff_and_low_00fe() {
+        let mut e = schip();
+        exec(&mut e, 0x00FF);
+        assert!(e.hires);
+        exec(&mut e, 0x00FE);
+        assert!(!e.hires);
+    }
+
+    #[test]
+    fn drw_dxy0_draws_large_sprites() {
+        let mut e = schip();
+        e.hires = true;
+        e.regs[0] = 1;
+        exec(&mut e, 0xF030);
+        assert_eq!(e.reg_i, e.large_sprite_addr(1));
+
+        e.reg_i = 0x300;
+        e.mem.0[0x300..0x320].copy_from_slice(&[0xFF; 32]);
+        exec(&mut e, 0xD000);
+        assert!(e.pixel(1 + 15, 1 + 15));
+        assert!(!e.pixel(1 + 16, 1));
+    }
+
+    #[test]
+    fn scroll_00cn_00fb_00fc() {
+        let mut e = schip();
+        e.hires = true;
+        e.screen[0] = 1;
+        exec(&mut e, 0x00C2);
+        assert!(e.pixel(0, 2));
+        exec(&mut e, 0x00FB);
+        assert!(e.pixel(4, 2));
+        exec(&mut e, 0x00FC);
+        exec(&mut e, 0x00FC);
+        assert!(e.screen.iter().all(|px| *px == 0));
+    }
+
+    #[test]
+    fn rpl_fx75_and_fx85_are_limited_to_8_flags() {
+        let mut e = schip();
+        for idx in 0..16 {
+            e.regs[idx] = idx + 1;
+        }
+        exec(&mut e, 0xFF75);
+        assert_eq!(&e.rpl[0..8], &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(e.rpl[8], 0);
+
+        e.regs = super::super::Regs::new();
+        exec(&mut e, 0xFF85);
+        assert_eq!(&e.regs.0[0..9], &[1, 2, 3, 4, 5, 6, 7, 8, 0]);
+    }
+
+    // XO-Chip
+    // -------
+
+    fn xochip() -> Chip8 {
+        Chip8::new(Platform::XoChip, Quirks::xochip())
+    }
+
+    #[test]
+    fn scu_00dn() {
+        let mut e = xochip();
+        e.hires = true;
+        e.screen[2 * 128] = 1;
+        exec(&mut e, 0x00D2);
+        assert!(e.pixel(0, 0));
+    }
+
+    #[test]
+    fn save_5xy2_and_load_5xy3_in_reverse() {
+        let mut e = xochip();
+        e.reg_i = 0x300;
+        e.regs[1] = 1;
+        e.regs[2] = 2;
+        e.regs[3] = 3;
+        exec(&mut e, 0x5312);
+        assert_eq!(&e.mem.0[0x300..0x303], &[3, 2, 1]);
+        assert_eq!(e.reg_i, 0x300);
+
+        exec(&mut e, 0x5413);
+        assert_eq!(&e.regs.0[1..5], &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn long_f000_loads_i() {
+        let mut e = xochip();
+        e.mem.0[0x202..0x204].copy_from_slice(&[0xAB, 0xCD]);
+        exec(&mut e, 0xF000);
+        assert_eq!(e.reg_i, 0xABCD);
+        assert_eq!(e.reg_pc, 0x204);
+    }
+
+    #[test]
+    fn plane_fn01_selects_planes() {
+        let mut e = xochip();
+        exec(&mut e, 0xF201);
+        assert_eq!(e.planes, 0b10);
+
+        e.reg_i = e.sprite_addr(0);
+        exec(&mut e, 0xD015);
+        assert_eq!(e.screen[0], 0b10);
+    }
+
+    #[test]
+    fn audio_f002_and_pitch_fx3a() {
+        let mut e = xochip();
+        e.reg_i = 0x300;
+        e.mem.0[0x300..0x310].copy_from_slice(&[0xAA; 16]);
+        e.regs[0] = 100;
+        exec(&mut e, 0xF002);
+        exec(&mut e, 0xF03A);
+        assert_eq!(e.pattern, [0xAA; 16]);
+        assert_eq!(e.pitch, 100);
+    }
+}
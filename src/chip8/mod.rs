@@ -1,14 +1,30 @@
+pub mod asm;
+mod audio;
+pub mod disasm;
 mod instruction;
 mod opcode;
+mod quirks;
+mod random;
+mod state;
 
 use bitvec::{BitArr, bitarr};
-use ggez::{graphics::*, input::keyboard::KeyCode, *};
 use log::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use std::ops::{Index, IndexMut};
 use std::time::Duration;
 
 use opcode::OpCode;
+use random::VipRandom;
+
+pub use quirks::Quirks;
+
+use crate::debugger::Debugger;
+use crate::framebuffer::{Framebuffer, PixelFormat};
+use crate::input::Input;
+use crate::savestate::StateError;
+use crate::FRAME_TIME;
 
 #[cfg(feature = "debug-view")]
 mod debug_view;
@@ -23,20 +39,104 @@ impl crate::debug_view::Debug for Chip8 {}
 const STACK_SIZE: usize = 16;
 const GP_AMOUNT: usize = 16;
 const MEM_SIZE: usize = 4 * 1024;
+const XO_MEM_SIZE: usize = 64 * 1024;
 
 const WIDTH: usize = 64;
 const HEIGHT: usize = 32;
 
+// Super Chip8 high resolution mode
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+
+// RPL user flags, Super Chip8 only offers 8 of these
+const RPL_AMOUNT: usize = 16;
+const SCHIP_RPL_AMOUNT: usize = 8;
+
+// XO-Chip audio pattern buffer
+const PATTERN_SIZE: usize = 16;
+
+// Location of the built-in sprites
+const SPRITE_ADDR: u16 = 0x00;
+const LARGE_SPRITE_ADDR: u16 = 0x50;
+
 // Chip8 cycles around 500Hz = 2 ms per cycle
 const CYCLE_TIME: Duration = Duration::from_millis(2);
-// Times cycle down at 60Hz = 16.6 ms per cycle
-const TIMER_TIME: Duration = Duration::from_nanos(16666666);
 
 // --------------- //
 // Data Structures //
 // --------------- //
 
+/// Chip8 variant to emulate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    /// The original Chip8
+    Chip8,
+    /// Super Chip8 1.1, which adds a high resolution mode, scrolling and large fonts
+    SuperChip,
+    /// XO-Chip, which extends Super Chip8 with more memory, a second bitplane and audio patterns
+    XoChip,
+}
+
+impl Platform {
+    /// Whether the Super Chip8 instructions are available.
+    pub fn superchip(self) -> bool {
+        match self {
+            Platform::Chip8 => false,
+            Platform::SuperChip | Platform::XoChip => true,
+        }
+    }
+
+    /// Whether the XO-Chip instructions are available.
+    pub fn xochip(self) -> bool {
+        self == Platform::XoChip
+    }
+
+    /// Quirks used by programs written for this platform.
+    pub fn default_quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::vip(),
+            Platform::SuperChip => Quirks::schip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+
+    fn mem_size(self) -> usize {
+        match self {
+            Platform::XoChip => XO_MEM_SIZE,
+            _ => MEM_SIZE,
+        }
+    }
+
+    fn rpl_amount(self) -> usize {
+        match self {
+            Platform::XoChip => RPL_AMOUNT,
+            _ => SCHIP_RPL_AMOUNT,
+        }
+    }
+
+    /// Amount of colours that can be shown, XO-Chip combines two bitplanes.
+    fn colours(self) -> u8 {
+        match self {
+            Platform::XoChip => 4,
+            _ => 2,
+        }
+    }
+
+    /// Size of the screen buffer.
+    ///
+    /// Platforms with a high resolution mode always use a high resolution buffer. In low
+    /// resolution mode, each pixel is drawn as a 2x2 block of this buffer.
+    fn screen_size(self) -> (usize, usize) {
+        match self {
+            Platform::Chip8 => (WIDTH, HEIGHT),
+            Platform::SuperChip | Platform::XoChip => (HIRES_WIDTH, HIRES_HEIGHT),
+        }
+    }
+}
+
 pub struct Chip8 {
+    platform: Platform,
+    quirks: Quirks,
     // Main Memory
     mem: Mem,
     // Stack
@@ -47,14 +147,36 @@ pub struct Chip8 {
     reg_pc: u16,             // Program counter (pseudo)
     reg_dt: u8,              // Delay timer
     reg_st: u8,              // Sound timer
+    rpl: [u8; RPL_AMOUNT],   // Super Chip8 RPL user flags
     // Graphics
-    screen: BitArr!(for WIDTH * HEIGHT),
+    screen: Vec<u8>,         // One byte per pixel, one bit per bitplane
+    hires: bool,             // Super Chip8 high resolution mode
+    planes: u8,              // XO-Chip bitplanes selected for drawing
+    vblank_wait: bool,       // Set when waiting for a display refresh after drawing
+    // Audio
+    pattern: [u8; PATTERN_SIZE], // XO-Chip audio pattern buffer
+    pitch: u8,                   // XO-Chip audio pattern playback rate
+    audio_phase: f64,            // Position in the pattern buffer, in bits
+    samples: Vec<i16>,           // Samples produced since they were last taken
     // Keypad
     keypad: BitArr!(for 16), // Keypad state
     await_press: Option<u8>, // Some(reg) if the emulator is waiting for a keypress
+    halted: bool,            // Set when the program exits
+    // Random numbers
+    seed: u64,               // Seed of the random number generator
+    rng: ChaCha8Rng,         // Used by Cxkk, seeded to make runs reproducible
+    vip_rng: VipRandom,      // Used by Cxkk instead of `rng` in VIP random mode
+    vip_random: bool,        // Use the random routine of the COSMAC VIP interpreter
+    // Debugging
+    debugger: Debugger,      // Breakpoints and watchpoints, not part of the emulator state
     // Timing
+    cycles: u64,             // Instructions executed since the rom was loaded
+    cycle_time: Duration,    // Time it takes to execute a single instruction
     cycle_timer: Duration,   // Elapsed time since last cycle
-    clock_timer: Duration    // Timer for sound / delay clocks
+    sample_timer: Duration,  // Elapsed time which did not fill an audio sample yet
+    clock_timer: Duration,   // Timer for sound / delay clocks
+    frame_locked: bool,      // Tick the timers after a fixed amount of instructions
+    frame_cycles: u32        // Instructions executed since the last tick, when frame locked
 }
 
 // Avoid constant typecasting in instructions
@@ -80,11 +202,17 @@ impl IndexMut<u8> for Regs {
     }
 }
 
-struct Mem([u8; MEM_SIZE]);
+// Addresses wrap around at the end of memory, memory sizes are always a power of two
+struct Mem(Vec<u8>);
 
 impl Mem {
-    pub fn new() -> Mem {
-        Mem([0x00; MEM_SIZE])
+    pub fn new(size: usize) -> Mem {
+        Mem(vec![0x00; size])
+    }
+
+    /// The address which is accessed when indexing with `index`.
+    fn wrap(&self, index: u16) -> u16 {
+        (index as usize & (self.0.len() - 1)) as u16
     }
 }
 
@@ -92,13 +220,14 @@ impl Index<u16> for Mem {
     type Output = u8;
 
     fn index(&self, index: u16) -> &Self::Output {
-        &self.0[index as usize]
+        &self.0[index as usize & (self.0.len() - 1)]
     }
 }
 
 impl IndexMut<u16> for Mem {
     fn index_mut(&mut self, index: u16) -> &mut Self::Output {
-        &mut self.0[index as usize]
+        let len = self.0.len();
+        &mut self.0[index as usize & (len - 1)]
     }
 }
 
@@ -115,93 +244,318 @@ impl crate::Emulator for Chip8 {
 
     fn advance(&mut self, elapsed: std::time::Duration) -> bool {
         self.cycle_timer += elapsed;
-        self.clock_timer += elapsed;
 
-        let mut draw = false;
+        let mut cycles = 0;
+        while self.cycle_timer >= self.cycle_time {
+            self.cycle_timer -= self.cycle_time;
+            cycles += 1;
+        }
 
-        while self.cycle_timer > CYCLE_TIME {
-            self.cycle_timer -= CYCLE_TIME;
-            draw = draw || self.cycle();
+        let draw = self.run(cycles);
+        if self.debugger.hit().is_some() {
+            self.cycle_timer = Duration::from_millis(0);
         }
+        draw
+    }
+
+    fn run(&mut self, cycles: u64) -> bool {
+        let mut draw = false;
+
+        // The sound timer only changes on a tick, so produce the audio up to every tick
+        let mut unsynthesized = Duration::from_millis(0);
+
+        for _ in 0..cycles {
+            // Stop in front of a breakpoint, unless the instruction is not executed yet anyway
+            if !self.idle() {
+                self.debugger.check_breakpoint(self.reg_pc);
+                if self.debugger.hit().is_some() {
+                    break;
+                }
+            }
 
-        while self.clock_timer > TIMER_TIME {
-            self.clock_timer -= TIMER_TIME;
-            if self.reg_dt > 0 {
-                self.reg_dt -= 1;
+            draw |= self.cycle();
+            self.cycles += 1;
+
+            // Timers follow the emulated time rather than the elapsed time, so the same inputs
+            // on the same cycles always lead to the same results.
+            unsynthesized += self.cycle_time;
+            let ticks = if self.frame_locked {
+                self.frame_cycles += 1;
+                if self.frame_cycles >= self.frame_length() {
+                    self.frame_cycles = 0;
+                    1
+                } else {
+                    0
+                }
+            } else {
+                self.clock_timer += self.cycle_time;
+                let mut ticks = 0;
+                while self.clock_timer >= FRAME_TIME {
+                    self.clock_timer -= FRAME_TIME;
+                    ticks += 1;
+                }
+                ticks
+            };
+
+            for _ in 0..ticks {
+                audio::synthesize(self, unsynthesized);
+                unsynthesized = Duration::from_millis(0);
+                self.tick();
             }
-            if self.reg_st > 0 {
-                self.reg_st -= 1;
+
+            if self.debugger.hit().is_some() {
+                break;
             }
         }
 
+        audio::synthesize(self, unsynthesized);
         draw
     }
 
+    fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        self.vip_rng = VipRandom::new(seed);
+    }
+
     fn cycle_dt(&self) -> std::time::Duration {
-        CYCLE_TIME
+        self.cycle_time
+    }
+
+    fn set_cycle_dt(&mut self, dt: std::time::Duration) {
+        // A cycle which takes no time at all would never end
+        self.cycle_time = dt.max(Duration::from_nanos(1));
+        self.snap_cycle_time();
+    }
+
+    fn key_down(&mut self, input: Input) {
+        match input {
+            Input::Keypad(key) => {
+                let idx = (key & 0x0F) as usize;
+                if let Some(r) = self.await_press {
+                    self.regs[r] = idx as u8;
+                    self.await_press = None;
+                }
+                self.keypad.set(idx, true);
+            }
+        }
     }
 
-    fn key_down(&mut self, key: KeyCode) {
-      if let Some(idx) = self.translate_key(key) {
-        if let Some(r) = self.await_press {
-          self.regs[r] = idx as u8;
-          self.await_press = None;
+    fn key_up(&mut self, input: Input) {
+        match input {
+            Input::Keypad(key) => self.keypad.set((key & 0x0F) as usize, false),
         }
-        self.keypad.set(idx, true);
-      }
     }
 
-    fn key_up(&mut self, key: KeyCode) {
-      if let Some(idx) = self.translate_key(key) {
-        self.keypad.set(idx, false);
-      }
+    fn audio(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
     }
 
     fn draw_size(&self) -> (f32, f32) {
-        (WIDTH as f32, HEIGHT as f32)
+        let (width, height) = self.platform.screen_size();
+        (width as f32, height as f32)
     }
 
-    fn draw(&self, ctx: &mut Context) -> GameResult<()> {
-        for idx in self.screen.as_bitslice().iter_ones() {
-            let x = idx % WIDTH;
-            let y = idx / WIDTH;
+    fn framebuffer(&self) -> Framebuffer<'_> {
+        let (width, height) = self.platform.screen_size();
+        let format = PixelFormat::Indexed(self.platform.colours());
+        Framebuffer::new(width, height, format, &self.screen)
+    }
 
-            crate::utils::draw_pixel(ctx, x, y, WHITE)?;
-        }
-        Ok(())
+    fn save_state(&self) -> Vec<u8> {
+        state::save(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        state::load(self, state)
+    }
+
+    fn pc(&self) -> u16 {
+        self.reg_pc
+    }
+
+    fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new(Platform::Chip8, Platform::Chip8.default_quirks())
     }
 }
 
 impl Chip8 {
-    pub fn new() -> Chip8 {
+    pub fn new(platform: Platform, quirks: Quirks) -> Chip8 {
+        let (width, height) = platform.screen_size();
+        let seed = rand::random();
         let mut res = Chip8 {
-            mem: Mem::new(),
+            platform,
+            quirks,
+            mem: Mem::new(platform.mem_size()),
             stack: Vec::with_capacity(STACK_SIZE),
             regs: Regs::new(),
             reg_i: 0x000,
             reg_pc: 0x200, // Programs start at 0x200
             reg_dt: 0x00,
             reg_st: 0x00,
-            screen: bitarr![0; WIDTH * HEIGHT],
+            rpl: [0x00; RPL_AMOUNT],
+            screen: vec![0; width * height],
+            hires: false,
+            planes: 0b01,
+            vblank_wait: false,
+            pattern: [0x00; PATTERN_SIZE],
+            pitch: 64, // 4000Hz playback rate
+            audio_phase: 0.0,
+            samples: Vec::new(),
             keypad: bitarr![0; 16],
             await_press: None,
+            halted: false,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            vip_rng: VipRandom::new(seed),
+            vip_random: false,
+            debugger: Debugger::new(),
+            cycles: 0,
+            cycle_time: CYCLE_TIME,
             cycle_timer: Duration::from_millis(0),
-            clock_timer: Duration::from_millis(0)
+            sample_timer: Duration::from_millis(0),
+            clock_timer: Duration::from_millis(0),
+            frame_locked: false,
+            frame_cycles: 0
         };
 
         res.load_sprites();
         res
     }
 
+    /// Change the emulation speed to a fixed amount of instructions per 60Hz frame.
+    pub fn set_cycles_per_frame(&mut self, cycles: u32) {
+        self.cycle_time = FRAME_TIME / cycles.max(1);
+    }
+
+    /// Change the emulation speed to a fixed amount of instructions per second.
+    ///
+    /// When frame locked, the speed is rounded to a whole amount of instructions per frame.
+    pub fn set_instructions_per_second(&mut self, ips: u32) {
+        self.cycle_time = Duration::from_secs(1) / ips.max(1);
+        self.snap_cycle_time();
+    }
+
+    /// Tick the timers after exactly as many instructions every 60Hz frame, instead of after
+    /// 16.6 ms of emulated time.
+    ///
+    /// Without rounding errors in between, every frame executes the same instructions on every
+    /// machine and at every speed.
+    pub fn set_frame_locked(&mut self, locked: bool) {
+        self.frame_locked = locked;
+        self.frame_cycles = 0;
+        self.snap_cycle_time();
+    }
+
+    pub fn frame_locked(&self) -> bool {
+        self.frame_locked
+    }
+
+    /// Produce the numbers of `Cxkk` with a routine modelled on the one of the COSMAC VIP
+    /// interpreter, instead of the seeded ChaCha8 generator.
+    pub fn set_vip_random(&mut self, vip: bool) {
+        self.vip_random = vip;
+    }
+
+    pub fn vip_random(&self) -> bool {
+        self.vip_random
+    }
+
+    // Next number of the random generator in use
+    fn random(&mut self) -> u8 {
+        if self.vip_random {
+            self.vip_rng.next(&self.mem.0[..0x100])
+        } else {
+            self.rng.random()
+        }
+    }
+
+    // Amount of instructions executed per frame, at the current speed
+    fn frame_length(&self) -> u32 {
+        let cycles = FRAME_TIME.as_secs_f64() / self.cycle_time.as_secs_f64();
+        (cycles.round() as u32).max(1)
+    }
+
+    // A frame locked emulator executes a whole amount of instructions per frame
+    fn snap_cycle_time(&mut self) {
+        if self.frame_locked {
+            self.cycle_time = FRAME_TIME / self.frame_length();
+        }
+    }
+
+    // A 60Hz timer tick, which also ends the display wait
+    fn tick(&mut self) {
+        self.vblank_wait = false;
+        if self.reg_dt > 0 {
+            self.reg_dt -= 1;
+        }
+        if self.reg_st > 0 {
+            self.reg_st -= 1;
+        }
+    }
+
+    /// Amount of instructions executed per second and per 60Hz frame, at the current speed.
+    pub fn speed(&self) -> (f64, f64) {
+        let cycle = self.cycle_time.as_secs_f64();
+        (1.0 / cycle, FRAME_TIME.as_secs_f64() / cycle)
+    }
+
 
     // Cycle
     // -----
 
     fn cycle(&mut self) -> bool {
-      match self.await_press {
-        None => self.fetch().decode().exec(self),
-        Some(_) => false
-      }
+        if self.idle() {
+            false
+        } else {
+            self.step()
+        }
+    }
+
+    // Halted, waiting for a key press or, after Dxyn with the display wait quirk, for the next
+    // timer tick
+    fn idle(&self) -> bool {
+        self.halted || self.await_press.is_some() || self.vblank_wait
+    }
+
+    fn step(&mut self) -> bool {
+        let pc = self.reg_pc;
+        let code = self.fetch();
+        let platform = self.platform;
+
+        // Registers are compared before and after the instruction, instead of observing every
+        // access, since nearly every instruction touches them.
+        let regs = if self.debugger.watches_registers() {
+            Some((self.regs.0, self.reg_i))
+        } else {
+            None
+        };
+
+        let draw = code.decode(platform).exec(self);
+
+        if let Some((regs, reg_i)) = regs {
+            self.observe_registers(regs, reg_i);
+        }
+        self.debugger.check_watchpoints(pc, || {
+            let instruction = code.decode(platform).to_string();
+            instruction.split_whitespace().collect::<Vec<_>>().join(" ")
+        });
+
+        draw
     }
 
     fn fetch(&mut self) -> OpCode {
@@ -212,16 +566,119 @@ impl Chip8 {
     }
 
     fn get_opcode(&self, idx: u16) -> OpCode {
-        OpCode::from_cells(self.mem[idx], self.mem[idx + 1])
+        OpCode::from_cells(self.mem[idx], self.mem[idx.wrapping_add(1)])
+    }
+
+    fn get_word(&self, idx: u16) -> u16 {
+        (self.mem[idx] as u16) << 8 | self.mem[idx.wrapping_add(1)] as u16
     }
 
 
+    // Observable Access
+    // -----------------
+
+    /// Read a byte of memory on behalf of an instruction, visible to watchpoints.
+    fn read(&mut self, addr: u16) -> u8 {
+        let addr = self.mem.wrap(addr);
+        let val = self.mem[addr];
+        self.debugger.read(addr, val);
+        val
+    }
+
+    /// Write a byte of memory on behalf of an instruction, visible to watchpoints.
+    fn write(&mut self, addr: u16, val: u8) {
+        let addr = self.mem.wrap(addr);
+        let old = self.mem[addr];
+        self.mem[addr] = val;
+        self.debugger.write(addr, old, val);
+    }
+
+    fn observe_registers(&mut self, regs: [u8; GP_AMOUNT], reg_i: u16) {
+        for (idx, (old, new)) in regs.iter().zip(self.regs.0.iter()).enumerate() {
+            if old != new {
+                let name = format!("v{:x}", idx);
+                self.debugger.write_register(&name, *old as u16, *new as u16);
+            }
+        }
+        if reg_i != self.reg_i {
+            self.debugger.write_register("i", reg_i, self.reg_i);
+        }
+    }
+
+
+    // Inspection
+    // ----------
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Check if the pixel at (`x`, `y`) of the screen buffer is lit.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let (width, _) = self.platform.screen_size();
+        self.screen[y * width + x] != 0
+    }
+
+    /// Whether the program exited through `00FD`.
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// General purpose registers, V0 to VF.
+    pub fn regs(&self) -> &[u8] {
+        &self.regs.0
+    }
+
+    pub fn reg_i(&self) -> u16 {
+        self.reg_i
+    }
+
+    pub fn reg_pc(&self) -> u16 {
+        self.reg_pc
+    }
+
+    pub fn reg_dt(&self) -> u8 {
+        self.reg_dt
+    }
+
+    pub fn reg_st(&self) -> u8 {
+        self.reg_st
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn mem(&self) -> &[u8] {
+        &self.mem.0
+    }
+
+    /// Memory, writable to set up the program before it runs.
+    pub fn mem_mut(&mut self) -> &mut [u8] {
+        &mut self.mem.0
+    }
+
     // Utilities
     // ---------
 
     #[inline]
     fn pc_inc(&mut self) {
-        self.reg_pc += 2;
+        self.reg_pc = self.reg_pc.wrapping_add(2);
+    }
+
+    /// Skip the next instruction.
+    ///
+    /// XO-Chip has a 4 byte instruction (`F000 nnnn`), which needs to be skipped as a whole.
+    #[inline]
+    fn skip(&mut self) {
+        if self.platform.xochip() && self.get_word(self.reg_pc) == 0xF000 {
+            self.pc_inc();
+        }
+        self.pc_inc();
     }
 
     #[inline]
@@ -234,37 +691,25 @@ impl Chip8 {
         self.regs[0xF] = 1;
     }
 
-    // Key Translation
-    // ---------------
-
-    /// Map a keycode onto a hex value.
-    ///
-    /// We map the layout of the chip8 hex keypad onto the qwerty keyboard.
-    ///
-    /// | key | key | key | key |
-    /// |---|---|---|---|
-    /// | 1 | 2 | 3 | C |
-    /// | 4 | 5 | 6 | D |
-    /// | 7 | 8 | 9 | E |
-    /// | A | 0 | B | F |
+    /// Amount of buffer pixels a scroll instruction moves per pixel.
     ///
-    /// is mapped onto
-    ///
-    /// | key | key | key | key |
-    /// |---|---|---|---|
-    /// | 1 | 2 | 3 | 4 |
-    /// | q | w | e | r |
-    /// | a | s | d | f |
-    /// | z | x | c | v |
-    ///
-    #[rustfmt::skip]
-    fn translate_key(&self, key: KeyCode) -> Option<usize> {
-        match key {
-          KeyCode::Key1 => Some(0x1), KeyCode::Key2 => Some(0x2), KeyCode::Key3 => Some(0x3), KeyCode::Key4 => Some(0xC),
-          KeyCode::Q    => Some(0x4), KeyCode::W    => Some(0x5), KeyCode::E    => Some(0x6), KeyCode::R    => Some(0xD),
-          KeyCode::A    => Some(0x7), KeyCode::S    => Some(0x8), KeyCode::D    => Some(0x9), KeyCode::F    => Some(0xE),
-          KeyCode::Z    => Some(0xA), KeyCode::X    => Some(0x0), KeyCode::C    => Some(0xB), KeyCode::V    => Some(0xF),
-          _ => None
+    /// Super Chip8 1.1 scrolls by high resolution pixels, even in low resolution mode.
+    #[inline]
+    fn scroll_scale(&self) -> usize {
+        if self.platform.xochip() {
+            self.pixel_scale()
+        } else {
+            1
+        }
+    }
+
+    /// Amount of buffer pixels used for a single pixel in each direction.
+    #[inline]
+    fn pixel_scale(&self) -> usize {
+        if self.platform.superchip() && !self.hires {
+            2
+        } else {
+            1
         }
     }
 
@@ -273,7 +718,12 @@ impl Chip8 {
 
     #[inline]
     fn sprite_addr(&self, digit: u8) -> u16 {
-        digit as u16 * 5
+        SPRITE_ADDR + digit as u16 * 5
+    }
+
+    #[inline]
+    fn large_sprite_addr(&self, digit: u8) -> u16 {
+        LARGE_SPRITE_ADDR + digit as u16 * 10
     }
 
     fn load_sprite(&mut self, digit: u8, sprite: &[u8; 5]) {
@@ -281,6 +731,11 @@ impl Chip8 {
         self.mem.0[addr..(addr + 5)].copy_from_slice(sprite);
     }
 
+    fn load_large_sprite(&mut self, digit: u8, sprite: &[u8; 10]) {
+        let addr = self.large_sprite_addr(digit) as usize;
+        self.mem.0[addr..(addr + 10)].copy_from_slice(sprite);
+    }
+
     fn load_sprites(&mut self) {
         self.load_sprite(0x0, &[0xF0, 0x90, 0x90, 0x90, 0xF0]);
         self.load_sprite(0x1, &[0x20, 0x60, 0x20, 0x20, 0x70]);
@@ -298,5 +753,29 @@ impl Chip8 {
         self.load_sprite(0xD, &[0xE0, 0x90, 0x90, 0x90, 0xE0]);
         self.load_sprite(0xE, &[0xF0, 0x80, 0xF0, 0x80, 0xF0]);
         self.load_sprite(0xF, &[0xF0, 0x80, 0xF0, 0x80, 0x80]);
+
+        if self.platform.superchip() {
+            self.load_large_sprites();
+        }
+    }
+
+    #[rustfmt::skip]
+    fn load_large_sprites(&mut self) {
+        self.load_large_sprite(0x0, &[0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF]);
+        self.load_large_sprite(0x1, &[0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF]);
+        self.load_large_sprite(0x2, &[0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF]);
+        self.load_large_sprite(0x3, &[0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF]);
+        self.load_large_sprite(0x4, &[0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03]);
+        self.load_large_sprite(0x5, &[0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF]);
+        self.load_large_sprite(0x6, &[0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF]);
+        self.load_large_sprite(0x7, &[0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18]);
+        self.load_large_sprite(0x8, &[0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF]);
+        self.load_large_sprite(0x9, &[0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF]);
+        self.load_large_sprite(0xA, &[0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3]);
+        self.load_large_sprite(0xB, &[0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC]);
+        self.load_large_sprite(0xC, &[0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C]);
+        self.load_large_sprite(0xD, &[0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC]);
+        self.load_large_sprite(0xE, &[0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF]);
+        self.load_large_sprite(0xF, &[0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0]);
     }
 }
@@ -3,6 +3,7 @@ use std::fmt;
 
 use super::instruction;
 use super::instruction::Instruction;
+use super::Platform;
 
 #[derive(Clone, Copy, Debug)]
 pub struct OpCode(u16);
@@ -10,6 +11,7 @@ pub struct OpCode(u16);
 #[derive(Debug)]
 pub enum Operands {
     Empty,                    // No operands
+    Const(u8),                // 4 bit constant (n)
     Address(u16),             // 12 bit address (nnn)
     Reg(u8),                  // Register name
     Regs(u8, u8),             // Register names
@@ -24,6 +26,7 @@ impl OpCode {
         OpCode((m1 << 8) | m2)
     }
 
+    #[allow(clippy::identity_op)]
     fn to_matchtup(self) -> (u8, u8, u8, u8) {
         (
             ((self.0 & 0xF000) >> 12) as u8,
@@ -33,8 +36,19 @@ impl OpCode {
         )
     }
 
-    pub fn decode(self) -> Instruction {
+    pub fn decode(self, platform: Platform) -> Instruction {
+        let schip = platform.superchip();
+        let xo = platform.xochip();
+
         let ins = match self.to_matchtup() {
+            // 00Cn (Super Chip8)
+            (0, 0, 0xC, _) if schip => {
+                Instruction::create(self, "SCD", decode_const(self), instruction::scd_00cn)
+            }
+            // 00Dn (XO-Chip)
+            (0, 0, 0xD, _) if xo => {
+                Instruction::create(self, "SCU", decode_const(self), instruction::scu_00dn)
+            }
             // 00E0
             (0, 0, 0xE, 0x0) => {
                 Instruction::create(self, "CLS", Operands::Empty, instruction::cls_00e0)
@@ -43,6 +57,26 @@ impl OpCode {
             (0, 0, 0xE, 0xE) => {
                 Instruction::create(self, "RET", Operands::Empty, instruction::ret_00ee)
             }
+            // 00FB (Super Chip8)
+            (0, 0, 0xF, 0xB) if schip => {
+                Instruction::create(self, "SCR", Operands::Empty, instruction::scr_00fb)
+            }
+            // 00FC (Super Chip8)
+            (0, 0, 0xF, 0xC) if schip => {
+                Instruction::create(self, "SCL", Operands::Empty, instruction::scl_00fc)
+            }
+            // 00FD (Super Chip8)
+            (0, 0, 0xF, 0xD) if schip => {
+                Instruction::create(self, "EXIT", Operands::Empty, instruction::exit_00fd)
+            }
+            // 00FE (Super Chip8)
+            (0, 0, 0xF, 0xE) if schip => {
+                Instruction::create(self, "LOW", Operands::Empty, instruction::low_00fe)
+            }
+            // 00FF (Super Chip8)
+            (0, 0, 0xF, 0xF) if schip => {
+                Instruction::create(self, "HIGH", Operands::Empty, instruction::high_00ff)
+            }
             //0nnn
             (0, _, _, _) => {
                 Instruction::create(self, "SYS", decode_addr(self), instruction::not_implemented)
@@ -67,6 +101,14 @@ impl OpCode {
             (5, _, _, 0) => {
                 Instruction::create(self, "SE", decode_regs(self), instruction::se_5xy0)
             }
+            // 5xy2 (XO-Chip)
+            (5, _, _, 2) if xo => {
+                Instruction::create(self, "SAVE", decode_regs(self), instruction::save_5xy2)
+            }
+            // 5xy3 (XO-Chip)
+            (5, _, _, 3) if xo => {
+                Instruction::create(self, "LOAD", decode_regs(self), instruction::load_5xy3)
+            }
             // 6xkk
             (6, _, _, _) => {
                 Instruction::create(self, "LD", decode_reg_const(self), instruction::ld_6xkk)
@@ -139,6 +181,18 @@ impl OpCode {
             (0xE, _, 0xA, 1) => {
                 Instruction::create(self, "SKNP", decode_reg(self), instruction::sknp_exa1)
             }
+            // F000 nnnn (XO-Chip)
+            (0xF, 0, 0, 0) if xo => {
+                Instruction::create(self, "LONG", Operands::Empty, instruction::long_f000)
+            }
+            // Fn01 (XO-Chip)
+            (0xF, _, 0, 1) if xo => {
+                Instruction::create(self, "PLANE", decode_x_const(self), instruction::plane_fn01)
+            }
+            // F002 (XO-Chip)
+            (0xF, 0, 0, 2) if xo => {
+                Instruction::create(self, "AUDIO", Operands::Empty, instruction::audio_f002)
+            }
             // Fx07
             (0xF, _, 0, 7) => {
                 Instruction::create(self, "LD", decode_reg(self), instruction::ld_fx07)
@@ -163,10 +217,18 @@ impl OpCode {
             (0xF, _, 2, 9) => {
                 Instruction::create(self, "LD", decode_reg(self), instruction::ld_fx29)
             }
+            // Fx30 (Super Chip8)
+            (0xF, _, 3, 0) if schip => {
+                Instruction::create(self, "LD", decode_reg(self), instruction::ld_fx30)
+            }
             // Fx33
             (0xF, _, 3, 3) => {
                 Instruction::create(self, "LD", decode_reg(self), instruction::ld_fx33)
             }
+            // Fx3A (XO-Chip)
+            (0xF, _, 3, 0xA) if xo => {
+                Instruction::create(self, "PITCH", decode_reg(self), instruction::pitch_fx3a)
+            }
             // Fx55
             (0xF, _, 5, 5) => {
                 Instruction::create(self, "LD", decode_reg(self), instruction::ld_fx55)
@@ -175,6 +237,14 @@ impl OpCode {
             (0xF, _, 6, 5) => {
                 Instruction::create(self, "LD", decode_reg(self), instruction::ld_fx65)
             }
+            // Fx75 (Super Chip8)
+            (0xF, _, 7, 5) if schip => {
+                Instruction::create(self, "LD", decode_reg(self), instruction::ld_fx75)
+            }
+            // Fx85 (Super Chip8)
+            (0xF, _, 8, 5) if schip => {
+                Instruction::create(self, "LD", decode_reg(self), instruction::ld_fx85)
+            }
             _ => {
                 warn!("Failed to decode: `{:#06X}`", self);
                 Instruction::create(self, "???", Operands::Empty, instruction::not_implemented)
@@ -189,6 +259,14 @@ impl OpCode {
 // Decoders //
 // -------- //
 
+fn decode_const(op: OpCode) -> Operands {
+    Operands::Const((op.0 & 0x000F) as u8)
+}
+
+fn decode_x_const(op: OpCode) -> Operands {
+    Operands::Const(((op.0 & 0x0F00) >> 8) as u8)
+}
+
 fn decode_addr(op: OpCode) -> Operands {
     Operands::Address(op.0 & 0x0FFF)
 }
@@ -205,6 +283,7 @@ fn decode_reg_const(op: OpCode) -> Operands {
     Operands::RegAndConst(((op.0 & 0x0F00) >> 8) as u8, (op.0 & 0x00FF) as u8)
 }
 
+#[allow(clippy::identity_op)]
 fn decode_regs_const(op: OpCode) -> Operands {
     Operands::RegsAndConst(
         ((op.0 & 0x0F00) >> 8) as u8,
@@ -228,6 +307,7 @@ impl fmt::Display for Operands {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Operands::Empty => write!(f, ""),
+            Operands::Const(cnst) => write!(f, "{:#03X}", cnst),
             Operands::Address(addr) => write!(f, "{:#03X}", addr),
             Operands::Reg(reg) => write!(f, "v{:X}", reg),
             Operands::Regs(regx, regy) => write!(f, "v{:X} v{:X}", regx, regy),
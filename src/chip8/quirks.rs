@@ -0,0 +1,61 @@
+/// Behaviour which differs between chip8 interpreters.
+///
+/// The presets follow the compatibility profiles of Octo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy6` and `8xyE` store the shifted value of VY in VX, instead of shifting VX in place
+    pub shift_vy: bool,
+    /// `Fx55` and `Fx65` increment I past the last register, instead of leaving it untouched
+    pub increment_i: bool,
+    /// `Bnnn` jumps to nnn + VX, where x is the highest nibble of nnn, instead of nnn + V0
+    pub jump_vx: bool,
+    /// Sprites are clipped at the edges of the screen, instead of wrapping around
+    pub clip: bool,
+    /// `8xy1`, `8xy2` and `8xy3` reset VF
+    pub logic_reset_vf: bool,
+    /// `Dxyn` waits for the next 60Hz display refresh before the next instruction is executed
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            increment_i: true,
+            jump_vx: false,
+            clip: true,
+            logic_reset_vf: true,
+            display_wait: true,
+        }
+    }
+
+    /// The CHIP-48 interpreter for the HP-48 calculator
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            increment_i: false,
+            jump_vx: true,
+            clip: true,
+            logic_reset_vf: false,
+            display_wait: false,
+        }
+    }
+
+    /// Super Chip8 1.1, which kept the behaviour of CHIP-48
+    pub fn schip() -> Quirks {
+        Quirks::chip48()
+    }
+
+    /// XO-Chip, as implemented by Octo
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            increment_i: true,
+            jump_vx: false,
+            clip: false,
+            logic_reset_vf: false,
+            display_wait: false,
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Pseudo-random number routine modelled on the one of the COSMAC VIP interpreter.
+//!
+//! The VIP interpreter has no real source of randomness: its RND routine walks a pointer through
+//! a page of its own memory, and mixes the byte it points at into the previous number. This
+//! generator does the same with the first page of the chip8 memory, which holds the interpreter
+//! on a VIP and the fonts in memu. Its numbers only depend on the bytes of that page and on how
+//! often `Cxkk` was executed, and they are far less random than those of the default generator.
+//!
+//! The exact numbers of a VIP depend on the bytes of the original interpreter, which memu does
+//! not include, so they are not reproduced.
+
+/// State of the routine: the pointer into the first page and the previous number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VipRandom {
+    pub pointer: u8,
+    pub value: u8,
+}
+
+impl VipRandom {
+    /// Start with a pointer and previous number derived from `seed`.
+    pub fn new(seed: u64) -> VipRandom {
+        VipRandom {
+            pointer: seed as u8,
+            value: (seed >> 8) as u8,
+        }
+    }
+
+    /// Produce the next number, reading the byte at the next pointer position of `page`.
+    pub fn next(&mut self, page: &[u8]) -> u8 {
+        self.pointer = self.pointer.wrapping_add(1);
+        let byte = page[self.pointer as usize];
+        self.value = self.value.rotate_right(1).wrapping_add(byte) ^ self.pointer;
+        self.value
+    }
+}
@@ -0,0 +1,235 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+use super::{Chip8, Mem, Regs, VipRandom, GP_AMOUNT, PATTERN_SIZE, RPL_AMOUNT, STACK_SIZE};
+
+// Bump when the layout below changes
+const VERSION: u8 = 8;
+
+// Marks an empty `await_press`, valid registers only go up to 0xF
+const NO_AWAIT: u8 = 0xFF;
+
+pub fn save(e: &Chip8) -> Vec<u8> {
+    let mut w = StateWriter::new();
+    w.u8(VERSION);
+
+    w.bytes(&e.mem.0);
+
+    w.u8(e.stack.len() as u8);
+    for addr in e.stack.iter() {
+        w.u16(*addr);
+    }
+
+    w.bytes(&e.regs.0);
+    w.u16(e.reg_i);
+    w.u16(e.reg_pc);
+    w.u8(e.reg_dt);
+    w.u8(e.reg_st);
+    w.bytes(&e.rpl);
+
+    w.bytes(&e.screen);
+    w.bool(e.hires);
+    w.u8(e.planes);
+    w.bool(e.vblank_wait);
+
+    w.bytes(&e.pattern);
+    w.u8(e.pitch);
+
+    let mut keypad = 0;
+    for idx in e.keypad.iter_ones() {
+        keypad |= 1 << idx;
+    }
+    w.u16(keypad);
+    w.u8(e.await_press.unwrap_or(NO_AWAIT));
+    w.bool(e.halted);
+
+    w.u64(e.seed);
+    w.bytes(&e.rng.get_seed());
+    w.u64(e.rng.get_stream());
+    w.u128(e.rng.get_word_pos());
+    w.u8(e.vip_rng.pointer);
+    w.u8(e.vip_rng.value);
+
+    w.u64(e.cycles);
+    w.duration(e.cycle_timer);
+    w.duration(e.clock_timer);
+    w.u32(e.frame_cycles);
+
+    w.into_bytes()
+}
+
+pub fn load(e: &mut Chip8, state: &[u8]) -> Result<(), StateError> {
+    let mut r = StateReader::new(state);
+
+    if r.u8()? != VERSION {
+        return Err(StateError::Invalid("chip8 state version"));
+    }
+
+    // Read everything before touching the emulator, so a failed load leaves it intact
+    let mut mem = Mem::new(e.platform.mem_size());
+    mem.0.copy_from_slice(r.bytes(e.platform.mem_size())?);
+
+    let depth = r.u8()? as usize;
+    if depth > STACK_SIZE {
+        return Err(StateError::Invalid("stack depth"));
+    }
+    let mut stack = Vec::with_capacity(STACK_SIZE);
+    for _ in 0..depth {
+        stack.push(r.u16()?);
+    }
+
+    let mut regs = Regs::new();
+    regs.0.copy_from_slice(r.bytes(GP_AMOUNT)?);
+    let reg_i = r.u16()?;
+    let reg_pc = r.u16()?;
+    let reg_dt = r.u8()?;
+    let reg_st = r.u8()?;
+    let mut rpl = [0; RPL_AMOUNT];
+    rpl.copy_from_slice(r.bytes(RPL_AMOUNT)?);
+
+    let (width, height) = e.platform.screen_size();
+    let screen = r.bytes(width * height)?.to_vec();
+    let hires = r.bool()?;
+    let planes = r.u8()?;
+    if planes > 0b11 {
+        return Err(StateError::Invalid("bitplanes"));
+    }
+    let vblank_wait = r.bool()?;
+
+    let mut pattern = [0; PATTERN_SIZE];
+    pattern.copy_from_slice(r.bytes(PATTERN_SIZE)?);
+    let pitch = r.u8()?;
+
+    let keypad = r.u16()?;
+    let await_press = match r.u8()? {
+        NO_AWAIT => None,
+        reg if (reg as usize) < GP_AMOUNT => Some(reg),
+        _ => return Err(StateError::Invalid("register")),
+    };
+    let halted = r.bool()?;
+
+    let seed = r.u64()?;
+    let mut rng_seed = [0; 32];
+    rng_seed.copy_from_slice(r.bytes(32)?);
+    let mut rng = ChaCha8Rng::from_seed(rng_seed);
+    rng.set_stream(r.u64()?);
+    rng.set_word_pos(r.u128()?);
+    let vip_rng = VipRandom {
+        pointer: r.u8()?,
+        value: r.u8()?,
+    };
+
+    let cycles = r.u64()?;
+    let cycle_timer = r.duration()?;
+    let clock_timer = r.duration()?;
+    let frame_cycles = r.u32()?;
+
+    e.mem = mem;
+    e.stack = stack;
+    e.regs = regs;
+    e.reg_i = reg_i;
+    e.reg_pc = reg_pc;
+    e.reg_dt = reg_dt;
+    e.reg_st = reg_st;
+    e.rpl = rpl;
+    e.screen = screen;
+    e.hires = hires;
+    e.planes = planes;
+    e.vblank_wait = vblank_wait;
+    e.pattern = pattern;
+    e.pitch = pitch;
+    for idx in 0..16 {
+        e.keypad.set(idx, keypad & (1 << idx) != 0);
+    }
+    e.await_press = await_press;
+    e.halted = halted;
+    e.seed = seed;
+    e.rng = rng;
+    e.vip_rng = vip_rng;
+    e.cycles = cycles;
+    e.cycle_timer = cycle_timer;
+    e.clock_timer = clock_timer;
+    e.frame_cycles = frame_cycles;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::super::{Chip8, Platform};
+    use crate::savestate::StateError;
+    use crate::Emulator;
+
+    // Starts the timers, draws a random digit, then calls itself forever
+    const ROM: [u8; 14] = [
+        0x60, 0x12, // LD V0, 0x12
+        0xC1, 0x0F, // RND V1, 0x0F
+        0xF0, 0x15, // LD DT, V0
+        0xF0, 0x18, // LD ST, V0
+        0xF1, 0x29, // LD F, V1
+        0xD0, 0x05, // DRW V0, V0, 5
+        0x22, 0x0C, // CALL 0x20C
+    ];
+
+    fn running_chip8() -> Chip8 {
+        let mut e = Chip8::new(Platform::XoChip, Platform::XoChip.default_quirks());
+        e.load_rom(ROM.to_vec());
+        e.seed(7);
+        e.run(100);
+        e
+    }
+
+    #[test]
+    fn loading_a_saved_state_restores_the_machine() {
+        let mut e = running_chip8();
+        let mut f = Chip8::new(Platform::XoChip, Platform::XoChip.default_quirks());
+        f.load_state(&e.save_state()).unwrap();
+
+        assert_eq!(f.mem.0, e.mem.0);
+        assert_eq!(f.stack, e.stack);
+        assert_eq!(f.regs.0, e.regs.0);
+        assert_eq!((f.reg_i, f.reg_pc), (e.reg_i, e.reg_pc));
+        assert_eq!((f.reg_dt, f.reg_st), (e.reg_dt, e.reg_st));
+        assert_ne!(e.reg_dt, 0);
+        assert_eq!(f.screen, e.screen);
+        assert!(e.screen.iter().any(|px| *px != 0));
+        assert_eq!(f.cycles(), e.cycles());
+        assert_eq!(f.seed, e.seed);
+        for _ in 0..8 {
+            assert_eq!(f.rng.random::<u64>(), e.rng.random::<u64>());
+        }
+
+        // Both machines continue identically
+        e.run(100);
+        f.run(100);
+        assert_eq!(f.save_state(), e.save_state());
+    }
+
+    #[test]
+    fn state_with_a_full_stack_can_be_loaded() {
+        let e = running_chip8();
+        assert_eq!(e.stack.len(), 16);
+
+        let mut f = Chip8::new(Platform::XoChip, Platform::XoChip.default_quirks());
+        assert!(f.load_state(&e.save_state()).is_ok());
+    }
+
+    #[test]
+    fn invalid_bitplanes_are_rejected() {
+        let mut e = running_chip8();
+        e.planes = 0b100;
+        let state = e.save_state();
+
+        let mut f = Chip8::new(Platform::XoChip, Platform::XoChip.default_quirks());
+        let before = f.save_state();
+        assert!(matches!(
+            f.load_state(&state),
+            Err(StateError::Invalid("bitplanes"))
+        ));
+        assert_eq!(f.save_state(), before);
+    }
+}
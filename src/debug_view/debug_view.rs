@@ -61,6 +61,7 @@ impl DebugView {
         }
     }
 
+    #[allow(clippy::borrowed_box)]
     pub fn draw(&mut self, emulator: &Box<dyn Emulator>) -> Result<(), Box<dyn Error>> {
         if let Some(inner) = self.0.as_mut() {
             inner.draw(emulator)?;
@@ -108,6 +109,7 @@ impl Inner {
     }
 
     #[inline]
+    #[allow(clippy::borrowed_box)]
     fn draw(&mut self, emulator: &Box<dyn Emulator>) -> Result<(), Box<dyn Error>> {
         // Disable logging while we draw the view
         logger::disable(self.log_handle.as_mut().unwrap());
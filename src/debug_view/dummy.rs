@@ -32,6 +32,7 @@ impl DebugView {
     pub fn log_handle(&mut self, _handle: flexi_logger::ReconfigurationHandle) {
     }
 
+    #[allow(clippy::borrowed_box)]
     pub fn draw(&mut self, _emulator: &Box<dyn Emulator>) -> Result<(), Box<dyn Error>> {
         Ok(())
     }
@@ -1,4 +1,5 @@
 #[cfg(feature = "debug-view")]
+#[allow(clippy::module_inception)]
 mod debug_view;
 #[cfg(not(feature = "debug-view"))]
 mod dummy;
@@ -0,0 +1,483 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Breakpoints and watchpoints of an emulator.
+///
+/// Emulators report the address of every instruction through `check_breakpoint` before they
+/// execute it, and report the memory and register accesses of that instruction before calling
+/// `check_watchpoints`. When a breakpoint or watchpoint is hit, the emulator stops executing
+/// instructions until the hit is taken by the frontend.
+#[derive(Default, Debug)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    // First access of the current instruction which triggered a watchpoint
+    access: Option<String>,
+    hit: Option<Hit>,
+    // Breakpoint the emulator stopped at, which is skipped once so the emulator can continue
+    resume: Option<u16>,
+    // Instruction selected in the debug view, the program counter when unset
+    cursor: Option<u16>,
+}
+
+/// Reason the emulator stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Hit {
+    /// The program counter reached a breakpoint
+    Breakpoint(u16),
+    /// The instruction at `pc` triggered a watchpoint
+    Watchpoint {
+        pc: u16,
+        instruction: String,
+        access: String,
+    },
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hit::Breakpoint(addr) => write!(f, "Breakpoint hit at {:#06X}", addr),
+            Hit::Watchpoint {
+                pc,
+                instruction,
+                access,
+            } => write!(
+                f,
+                "Watchpoint hit: {} by `{}` at {:#06X}",
+                access, instruction, pc
+            ),
+        }
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Default::default()
+    }
+
+    // Breakpoints
+    // -----------
+
+    pub fn set_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn clear_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Set a breakpoint if none is present, clear it otherwise. Returns whether it is now set.
+    pub fn toggle_breakpoint(&mut self, addr: u16) -> bool {
+        if !self.breakpoints.remove(&addr) {
+            self.breakpoints.insert(addr);
+            return true;
+        }
+        false
+    }
+
+    pub fn is_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    /// All breakpoints, in ascending order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Record a hit if `pc` is a breakpoint, before the instruction at `pc` is executed.
+    pub fn check_breakpoint(&mut self, pc: u16) {
+        if self.resume.take() == Some(pc) {
+            return;
+        }
+        if self.breakpoints.contains(&pc) {
+            self.hit = Some(Hit::Breakpoint(pc));
+        }
+    }
+
+    /// Do not stop at a breakpoint on `pc` the next time it is checked, as the emulator is
+    /// already there.
+    pub fn resume(&mut self, pc: u16) {
+        self.resume = Some(pc);
+    }
+
+    // Cursor
+    // ------
+
+    /// Address of the instruction selected in the debug view, if it was moved off the program
+    /// counter.
+    pub fn cursor(&self) -> Option<u16> {
+        self.cursor
+    }
+
+    /// Move the cursor by `instructions` from its current address, starting from `pc` if it was
+    /// not moved yet.
+    pub fn move_cursor(&mut self, pc: u16, instructions: i16) {
+        let addr = self.cursor.unwrap_or(pc);
+        self.cursor = Some(addr.wrapping_add((instructions * 2) as u16));
+    }
+
+    /// Put the cursor back on the program counter.
+    pub fn reset_cursor(&mut self) {
+        self.cursor = None;
+    }
+
+    // Watchpoints
+    // -----------
+
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Whether register writes need to be reported.
+    pub fn watches_registers(&self) -> bool {
+        self.watchpoints
+            .iter()
+            .any(|w| matches!(w.target, Target::Register(_)))
+    }
+
+    /// Report a read from memory.
+    pub fn read(&mut self, addr: u16, val: u8) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+        let target = |t: &Target| t.contains(addr);
+        if self.triggers(target, Access::Read, None, val as u16) {
+            self.trigger(format!("read of {:#04X} from {:#06X}", val, addr));
+        }
+    }
+
+    /// Report a write to memory.
+    pub fn write(&mut self, addr: u16, old: u8, new: u8) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+        let target = |t: &Target| t.contains(addr);
+        if self.triggers(target, Access::Write, Some(old as u16), new as u16) {
+            self.trigger(format!(
+                "write of {:#04X} to {:#06X} (was {:#04X})",
+                new, addr, old
+            ));
+        }
+    }
+
+    /// Report a write to the register called `name`, names are lowercase.
+    pub fn write_register(&mut self, name: &str, old: u16, new: u16) {
+        let target = |t: &Target| matches!(t, Target::Register(reg) if reg == name);
+        if self.triggers(target, Access::Write, Some(old), new) {
+            self.trigger(format!(
+                "write of {:#04X} to {} (was {:#04X})",
+                new, name, old
+            ));
+        }
+    }
+
+    fn triggers<F>(&self, target: F, access: Access, old: Option<u16>, new: u16) -> bool
+    where
+        F: Fn(&Target) -> bool,
+    {
+        self.watchpoints
+            .iter()
+            .any(|w| target(&w.target) && w.access.includes(access) && w.condition.holds(old, new))
+    }
+
+    fn trigger(&mut self, access: String) {
+        if self.access.is_none() {
+            self.access = Some(access);
+        }
+    }
+
+    /// Record a hit if the instruction at `pc` triggered a watchpoint.
+    ///
+    /// `instruction` is only called to describe the instruction when this is the case.
+    pub fn check_watchpoints<F: FnOnce() -> String>(&mut self, pc: u16, instruction: F) {
+        if let Some(access) = self.access.take() {
+            self.hit = Some(Hit::Watchpoint {
+                pc,
+                instruction: instruction(),
+                access,
+            });
+        }
+    }
+
+    // Hits
+    // ----
+
+    /// The breakpoint or watchpoint which was hit since the last call to `take_hit`, if any.
+    pub fn hit(&self) -> Option<&Hit> {
+        self.hit.as_ref()
+    }
+
+    /// Take the hit, after which the emulator continues from the breakpoint that was hit.
+    pub fn take_hit(&mut self) -> Option<Hit> {
+        if let Some(Hit::Breakpoint(pc)) = self.hit {
+            self.resume(pc);
+        }
+        self.hit.take()
+    }
+}
+
+// ----------- //
+// Watchpoints //
+// ----------- //
+
+/// Pauses the emulator when a location is accessed.
+///
+/// Written as `<target>[:<access>][:<condition>]`, e.g. `300-30F:w:==0x10` or `vf:changed`:
+///
+/// - target: a (hexadecimal) memory address, an inclusive range of addresses (`300-30F`), or
+///   the name of a register (`v0` to `vf` or `i` on chip8)
+/// - access: `r`, `w` or `rw`, defaults to `w`; registers can only be watched for writes
+/// - condition: `==<hex value>` or `changed`, which only applies to writes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub target: Target,
+    pub access: Access,
+    pub condition: Condition,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// An inclusive range of memory addresses
+    Memory(u16, u16),
+    /// A register, by its lowercase name
+    Register(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    /// Every access
+    Always,
+    /// Accesses which read or write a specific value
+    Equals(u16),
+    /// Writes which change the value
+    Changed,
+}
+
+impl Target {
+    fn contains(&self, addr: u16) -> bool {
+        match self {
+            Target::Memory(start, end) => (*start..=*end).contains(&addr),
+            Target::Register(_) => false,
+        }
+    }
+}
+
+impl Access {
+    fn includes(self, other: Access) -> bool {
+        self == Access::ReadWrite || self == other
+    }
+}
+
+impl Condition {
+    fn holds(self, old: Option<u16>, new: u16) -> bool {
+        match self {
+            Condition::Always => true,
+            Condition::Equals(val) => new == val,
+            Condition::Changed => old.is_some_and(|old| old != new),
+        }
+    }
+}
+
+impl std::str::FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Watchpoint, String> {
+        let mut parts = s.split(':');
+        let target = parts.next().unwrap_or_default().to_ascii_lowercase();
+
+        let target = match target.split_once('-') {
+            Some((start, end)) => match (parse_address(start)?, parse_address(end)?) {
+                (start, end) if start > end => {
+                    return Err(format!("`{}` ends before it starts", target))
+                }
+                (start, end) => Target::Memory(start, end),
+            },
+            None => match parse_address(&target) {
+                Ok(addr) => Target::Memory(addr, addr),
+                Err(_) if is_register(&target) => Target::Register(target),
+                Err(_) => {
+                    let msg = format!("`{}` is not an address, a range or a register", target);
+                    return Err(msg);
+                }
+            },
+        };
+
+        let mut access = Access::Write;
+        let mut condition = Condition::Always;
+
+        for part in parts {
+            match part {
+                "r" => access = Access::Read,
+                "w" => access = Access::Write,
+                "rw" => access = Access::ReadWrite,
+                "changed" => condition = Condition::Changed,
+                _ => match part.strip_prefix("==") {
+                    Some(val) => condition = Condition::Equals(parse_value(val)?),
+                    None => return Err(format!("`{}` is not an access or condition", part)),
+                },
+            }
+        }
+
+        if let Target::Register(_) = target {
+            if access != Access::Write {
+                return Err(String::from("registers can only be watched for writes"));
+            }
+        }
+        if condition == Condition::Changed && access == Access::Read {
+            return Err(String::from("`changed` only applies to writes"));
+        }
+
+        Ok(Watchpoint {
+            target,
+            access,
+            condition,
+        })
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.target {
+            Target::Memory(start, end) if start == end => write!(f, "{:#05X}", start)?,
+            Target::Memory(start, end) => write!(f, "{:#05X}-{:#05X}", start, end)?,
+            Target::Register(name) => write!(f, "{}", name)?,
+        }
+        match self.access {
+            Access::Read => write!(f, ":r")?,
+            Access::Write => write!(f, ":w")?,
+            Access::ReadWrite => write!(f, ":rw")?,
+        }
+        match self.condition {
+            Condition::Always => Ok(()),
+            Condition::Equals(val) => write!(f, ":=={:#04X}", val),
+            Condition::Changed => write!(f, ":changed"),
+        }
+    }
+}
+
+/// Parse an address written in hexadecimal, with an optional `$` or `0x` prefix.
+pub fn parse_address(s: &str) -> Result<u16, String> {
+    let hex = s
+        .strip_prefix('$')
+        .or_else(|| s.strip_prefix("0x"))
+        .unwrap_or(s);
+    u16::from_str_radix(hex, 16).map_err(|_| format!("`{}` is not a hexadecimal address", s))
+}
+
+fn parse_value(s: &str) -> Result<u16, String> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    u16::from_str_radix(hex, 16).map_err(|_| format!("`{}` is not a hexadecimal value", s))
+}
+
+// Registers which can be watched: `v0` to `vf` and `i` of the chip8
+fn is_register(name: &str) -> bool {
+    match name.strip_prefix('v') {
+        Some(idx) => idx.len() == 1 && idx.chars().all(|c| c.is_ascii_hexdigit()),
+        None => name == "i",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, Condition, Debugger, Target, Watchpoint};
+
+    fn parse(s: &str) -> Result<Watchpoint, String> {
+        s.parse()
+    }
+
+    fn target(s: &str) -> Target {
+        parse(s).unwrap().target
+    }
+
+    #[test]
+    fn cursor_moves_from_the_program_counter() {
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.cursor(), None);
+
+        debugger.move_cursor(0x200, 1);
+        assert_eq!(debugger.cursor(), Some(0x202));
+        // Once moved, the cursor no longer follows the program counter
+        debugger.move_cursor(0x300, -2);
+        assert_eq!(debugger.cursor(), Some(0x1FE));
+
+        debugger.reset_cursor();
+        debugger.move_cursor(0x000, -1);
+        assert_eq!(debugger.cursor(), Some(0xFFFE));
+    }
+
+    #[test]
+    fn addresses_and_ranges() {
+        assert_eq!(target("300"), Target::Memory(0x300, 0x300));
+        assert_eq!(target("0x300"), Target::Memory(0x300, 0x300));
+        assert_eq!(target("$300"), Target::Memory(0x300, 0x300));
+        assert_eq!(target("300-30F"), Target::Memory(0x300, 0x30F));
+        assert_eq!(target("300-300"), Target::Memory(0x300, 0x300));
+    }
+
+    #[test]
+    fn reversed_ranges_are_rejected() {
+        assert!(parse("30F-300").is_err());
+    }
+
+    #[test]
+    fn registers() {
+        assert_eq!(target("v0"), Target::Register("v0".into()));
+        assert_eq!(target("VF"), Target::Register("vf".into()));
+        assert_eq!(target("i"), Target::Register("i".into()));
+    }
+
+    #[test]
+    fn unknown_targets_are_rejected() {
+        for target in ["vg", "foo", "v", "v10", "", "0x0x300", "$$300", "300-"] {
+            assert!(parse(target).is_err(), "`{}` was accepted", target);
+        }
+    }
+
+    #[test]
+    fn access_and_condition() {
+        let w = parse("300-30F:rw:==0x10").unwrap();
+        assert_eq!(w.access, Access::ReadWrite);
+        assert_eq!(w.condition, Condition::Equals(0x10));
+
+        let w = parse("vf:changed").unwrap();
+        assert_eq!(w.access, Access::Write);
+        assert_eq!(w.condition, Condition::Changed);
+
+        let w = parse("300:r").unwrap();
+        assert_eq!(w.access, Access::Read);
+        assert_eq!(w.condition, Condition::Always);
+    }
+
+    #[test]
+    fn invalid_accesses_and_conditions_are_rejected() {
+        for s in ["300:x", "300:==zz", "v0:r", "i:rw", "300:r:changed"] {
+            assert!(parse(s).is_err(), "`{}` was accepted", s);
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in ["0x300:w", "0x300-0x30F:rw:==0x10", "vf:w:changed", "i:w"] {
+            assert_eq!(parse(s).unwrap().to_string(), s);
+        }
+    }
+}
@@ -0,0 +1,49 @@
+/// Layout of the pixels in a [`Framebuffer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// One byte per pixel, which contains an index into a palette with the given amount of colours
+    Indexed(u8),
+}
+
+/// Renderer-independent view on the display of an emulator.
+///
+/// Pixels are stored row by row, starting from the top left corner.
+#[derive(Clone, Copy, Debug)]
+pub struct Framebuffer<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub format: PixelFormat,
+    pub pixels: &'a [u8],
+}
+
+impl<'a> Framebuffer<'a> {
+    pub fn new(width: usize, height: usize, format: PixelFormat, pixels: &'a [u8]) -> Self {
+        debug_assert_eq!(width * height, pixels.len());
+        Framebuffer {
+            width,
+            height,
+            format,
+            pixels,
+        }
+    }
+
+    #[inline]
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Pixels of the framebuffer enlarged so every pixel becomes a `scale` by `scale` square.
+    pub fn scaled(&self, scale: usize) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(self.pixels.len() * scale * scale);
+        for row in self.pixels.chunks(self.width) {
+            let row: Vec<u8> = row
+                .iter()
+                .flat_map(|px| std::iter::repeat_n(*px, scale))
+                .collect();
+            for _ in 0..scale {
+                pixels.extend_from_slice(&row);
+            }
+        }
+        pixels
+    }
+}
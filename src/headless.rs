@@ -0,0 +1,58 @@
+use log::*;
+
+use super::movie::Playback;
+use super::{Emulator, FRAME_TIME};
+
+/// Amount of emulated time a headless run lasts.
+#[derive(Clone, Copy, Debug)]
+pub enum Limit {
+    /// Execute a fixed amount of cycles
+    Cycles(u64),
+    /// Emulate a fixed amount of 60Hz frames
+    Frames(u64),
+}
+
+/// Drive an emulator without a window until `limit` is reached.
+///
+/// The emulator is only advanced; it is up to the caller to inspect its state afterwards.
+/// The inputs of `playback` are fed to the emulator and the audio produced by the emulator is
+/// appended to `audio`, when they are present. `on_advance` is called after every step, along
+/// with whether the emulator requested a redraw.
+/// Returns the amount of times the emulator requested a redraw.
+pub fn run(
+    emulator: &mut dyn Emulator,
+    limit: Limit,
+    mut playback: Option<&mut Playback>,
+    mut audio: Option<&mut Vec<i16>>,
+    mut on_advance: impl FnMut(&dyn Emulator, bool),
+) -> u64 {
+    let (steps, dt) = match limit {
+        Limit::Cycles(n) => (n, emulator.cycle_dt()),
+        Limit::Frames(n) => (n, FRAME_TIME),
+    };
+
+    let mut redraws = 0;
+    for _ in 0..steps {
+        let draw = match playback.as_mut() {
+            Some(playback) => playback.advance(emulator, dt),
+            None => emulator.advance(dt),
+        };
+        if draw {
+            redraws += 1;
+        }
+        on_advance(emulator, draw);
+
+        // There is nobody to pause for, so only report breakpoints and watchpoints
+        if let Some(hit) = emulator.debugger_mut().take_hit() {
+            info!("{}", hit);
+        }
+
+        let samples = emulator.audio();
+        if let Some(audio) = audio.as_mut() {
+            audio.extend(samples);
+        }
+    }
+
+    info!("Headless run finished after {:?}, {} redraws", limit, redraws);
+    redraws
+}
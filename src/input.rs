@@ -0,0 +1,6 @@
+/// Emulator-level input, independent of the frontend which produces it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Input {
+    /// A key on a hexadecimal keypad (`0x0` to `0xF`)
+    Keypad(u8),
+}
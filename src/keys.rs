@@ -0,0 +1,412 @@
+use ggez::event::{Axis, Button, GamepadId};
+use ggez::input::keyboard::KeyCode;
+use serde::Deserialize;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use super::input::Input;
+use super::profile::{Profile, ProfileError};
+
+/// Keyboard keys mapped onto the action they trigger.
+pub type KeyMap = HashMap<KeyCode, Binding>;
+
+/// Gamepad buttons mapped onto the action they trigger.
+pub type ButtonMap = HashMap<Button, Binding>;
+
+/// Everything the keyboard and gamepads can do.
+pub struct Bindings {
+    pub keys: KeyMap,
+    pub buttons: ButtonMap,
+}
+
+// -------- //
+// Bindings //
+// -------- //
+
+/// Action of the frontend, which controls the emulation rather than the emulated program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Control {
+    /// Close the emulator
+    Quit,
+    /// Switch between normal, cycle and frame mode
+    Mode,
+    /// Speed up emulation in normal mode
+    Faster,
+    /// Slow down emulation in normal mode
+    Slower,
+    /// Execute more instructions per frame, without speeding up the timers
+    CpuFaster,
+    /// Execute fewer instructions per frame, without slowing down the timers
+    CpuSlower,
+    /// Execute a cycle or a frame in cycle or frame mode
+    Step,
+    /// Run the emulation backwards while the key is held
+    Rewind,
+    /// Save the screen to a PNG file
+    Screenshot,
+    /// Toggle a breakpoint on the selected instruction, clear all breakpoints with `<shift>`
+    Breakpoint,
+    /// Select the previous instruction in the debug view
+    CursorUp,
+    /// Select the next instruction in the debug view
+    CursorDown,
+    /// Load the state in a slot, save the state to it with `<shift>`
+    State(u8),
+    /// Select the `<shift>` action of the other controls while held, for gamepads
+    Shift,
+}
+
+impl FromStr for Control {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Control, ()> {
+        let control = match s {
+            "quit" => Control::Quit,
+            "mode" => Control::Mode,
+            "faster" => Control::Faster,
+            "slower" => Control::Slower,
+            "cpu-faster" => Control::CpuFaster,
+            "cpu-slower" => Control::CpuSlower,
+            "step" => Control::Step,
+            "rewind" => Control::Rewind,
+            "screenshot" => Control::Screenshot,
+            "breakpoint" => Control::Breakpoint,
+            "cursor-up" => Control::CursorUp,
+            "cursor-down" => Control::CursorDown,
+            "shift" => Control::Shift,
+            s => match s.strip_prefix("state").and_then(|slot| slot.parse().ok()) {
+                Some(slot @ 1..=9) => Control::State(slot),
+                _ => return Err(()),
+            },
+        };
+        Ok(control)
+    }
+}
+
+impl fmt::Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Control::Quit => f.pad("quit"),
+            Control::Mode => f.pad("mode"),
+            Control::Faster => f.pad("faster"),
+            Control::Slower => f.pad("slower"),
+            Control::CpuFaster => f.pad("cpu-faster"),
+            Control::CpuSlower => f.pad("cpu-slower"),
+            Control::Step => f.pad("step"),
+            Control::Rewind => f.pad("rewind"),
+            Control::Screenshot => f.pad("screenshot"),
+            Control::Breakpoint => f.pad("breakpoint"),
+            Control::CursorUp => f.pad("cursor-up"),
+            Control::CursorDown => f.pad("cursor-down"),
+            Control::State(slot) => f.pad(&format!("state{}", slot)),
+            Control::Shift => f.pad("shift"),
+        }
+    }
+}
+
+/// What a key does: press a key of the emulator, control the frontend or nothing at all.
+///
+/// Written as a hexadecimal keypad key, the name of a [`Control`] or `none` in rom profiles and
+/// on the command line.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "RawBinding")]
+pub enum Binding {
+    Input(Input),
+    Control(Control),
+    /// Removes a binding of the default layout
+    None,
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Input(Input::Keypad(hex)) => f.pad(&format!("keypad {:X}", hex)),
+            Binding::Control(control) => control.fmt(f),
+            Binding::None => f.pad("none"),
+        }
+    }
+}
+
+// Bindings are written as a number or a string in rom profiles
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawBinding {
+    Keypad(u8),
+    Name(String),
+}
+
+impl FromStr for Binding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Binding, ()> {
+        if s == "none" {
+            return Ok(Binding::None);
+        }
+
+        match u8::from_str_radix(s.strip_prefix("0x").unwrap_or(s), 16) {
+            Ok(hex) if hex <= 0xF => Ok(Binding::Input(Input::Keypad(hex))),
+            Ok(_) => Err(()),
+            Err(_) => s.parse().map(Binding::Control),
+        }
+    }
+}
+
+impl TryFrom<RawBinding> for Binding {
+    type Error = String;
+
+    fn try_from(raw: RawBinding) -> Result<Binding, String> {
+        match raw {
+            RawBinding::Keypad(hex) if hex <= 0xF => Ok(Binding::Input(Input::Keypad(hex))),
+            RawBinding::Keypad(hex) => Err(format!("keypad key {:#X} does not exist", hex)),
+            RawBinding::Name(name) => name
+                .parse()
+                .map_err(|_| format!("unknown key binding `{}`", name)),
+        }
+    }
+}
+
+/// Parse a `<key>=<binding>` key or button binding, as passed on the command line.
+pub fn parse_key_binding(s: &str) -> Result<(String, Binding), ProfileError> {
+    let err = || ProfileError::Key(s.to_string());
+    let (key, binding) = s.split_once('=').ok_or_else(err)?;
+    Ok((key.to_string(), binding.parse().map_err(|_| err())?))
+}
+
+// ------- //
+// Keymaps //
+// ------- //
+
+/// Default mapping of keycodes onto emulator inputs and controls.
+///
+/// We map the layout of a hex keypad onto the qwerty keyboard.
+///
+/// | key | key | key | key |
+/// |---|---|---|---|
+/// | 1 | 2 | 3 | C |
+/// | 4 | 5 | 6 | D |
+/// | 7 | 8 | 9 | E |
+/// | A | 0 | B | F |
+///
+/// is mapped onto
+///
+/// | key | key | key | key |
+/// |---|---|---|---|
+/// | 1 | 2 | 3 | 4 |
+/// | q | w | e | r |
+/// | a | s | d | f |
+/// | z | x | c | v |
+///
+#[rustfmt::skip]
+fn default_keymap() -> KeyMap {
+    let layout = [
+      (KeyCode::Key1, 0x1), (KeyCode::Key2, 0x2), (KeyCode::Key3, 0x3), (KeyCode::Key4, 0xC),
+      (KeyCode::Q,    0x4), (KeyCode::W,    0x5), (KeyCode::E,    0x6), (KeyCode::R,    0xD),
+      (KeyCode::A,    0x7), (KeyCode::S,    0x8), (KeyCode::D,    0x9), (KeyCode::F,    0xE),
+      (KeyCode::Z,    0xA), (KeyCode::X,    0x0), (KeyCode::C,    0xB), (KeyCode::V,    0xF),
+    ];
+    let controls = [
+        (KeyCode::Escape, Control::Quit),
+        (KeyCode::Slash, Control::Mode),
+        (KeyCode::Period, Control::Faster),
+        (KeyCode::Comma, Control::Slower),
+        (KeyCode::RBracket, Control::CpuFaster),
+        (KeyCode::LBracket, Control::CpuSlower),
+        (KeyCode::Space, Control::Step),
+        (KeyCode::Back, Control::Rewind),
+        (KeyCode::F12, Control::Screenshot),
+        (KeyCode::B, Control::Breakpoint),
+        (KeyCode::Up, Control::CursorUp),
+        (KeyCode::Down, Control::CursorDown),
+        (KeyCode::F1, Control::State(1)), (KeyCode::F2, Control::State(2)),
+        (KeyCode::F3, Control::State(3)), (KeyCode::F4, Control::State(4)),
+        (KeyCode::F5, Control::State(5)), (KeyCode::F6, Control::State(6)),
+        (KeyCode::F7, Control::State(7)), (KeyCode::F8, Control::State(8)),
+        (KeyCode::F9, Control::State(9)),
+    ];
+
+    let layout = layout.iter().map(|(key, hex)| (*key, Binding::Input(Input::Keypad(*hex))));
+    let controls = controls.iter().map(|(key, control)| (*key, Binding::Control(*control)));
+    layout.chain(controls).collect()
+}
+
+/// Default mapping of gamepad buttons onto emulator inputs.
+///
+/// The d-pad is mapped onto `5`, `7`, `8` and `9`, which many games use for movement, while the
+/// bottom and right action buttons press `6` and `4`.
+fn default_buttonmap() -> ButtonMap {
+    let layout = [
+        (Button::DPadUp, 0x5),
+        (Button::DPadDown, 0x8),
+        (Button::DPadLeft, 0x7),
+        (Button::DPadRight, 0x9),
+        (Button::South, 0x6),
+        (Button::East, 0x4),
+    ];
+    layout
+        .iter()
+        .map(|(btn, hex)| (*btn, Binding::Input(Input::Keypad(*hex))))
+        .collect()
+}
+
+impl Bindings {
+    /// Add the key and button bindings of a profile to the default layouts.
+    pub fn new(profile: &Profile) -> Result<Bindings, ProfileError> {
+        Ok(Bindings {
+            keys: bind(default_keymap(), &profile.keys, KEY_NAMES)?,
+            buttons: bind(default_buttonmap(), &profile.buttons, BUTTON_NAMES)?,
+        })
+    }
+
+    /// Describe which keys and buttons trigger which action, one action per line.
+    pub fn describe(&self) -> String {
+        let mut actions: BTreeMap<Binding, Vec<String>> = BTreeMap::new();
+        for (key, binding) in &self.keys {
+            let name = to_name(KEY_NAMES, *key).to_string();
+            actions.entry(*binding).or_default().push(name);
+        }
+        for (btn, binding) in &self.buttons {
+            let name = format!("pad {}", to_name(BUTTON_NAMES, *btn));
+            actions.entry(*binding).or_default().push(name);
+        }
+
+        let mut description = String::new();
+        for (action, mut names) in actions {
+            names.sort_unstable();
+            description += &format!("{:<12} {}\n", action, names.join(", "));
+        }
+        description
+    }
+}
+
+fn bind<K: Copy + Eq + Hash>(
+    mut map: HashMap<K, Binding>,
+    bindings: &BTreeMap<String, Binding>,
+    names: &[(&str, K)],
+) -> Result<HashMap<K, Binding>, ProfileError> {
+    for (name, binding) in bindings {
+        let key = from_name(names, name).ok_or_else(|| ProfileError::Key(name.to_string()))?;
+        match binding {
+            Binding::None => map.remove(&key),
+            binding => map.insert(key, *binding),
+        };
+    }
+
+    Ok(map)
+}
+
+// ----- //
+// Stick //
+// ----- //
+
+/// Turns the analog sticks and d-pad axes of gamepads into presses of the d-pad buttons.
+///
+/// The presses are kept apart from those of the real buttons, so releasing the stick does not
+/// release a d-pad button which is still held.
+#[derive(Default)]
+pub struct Stick {
+    pressed: HashSet<(GamepadId, Button)>,
+}
+
+impl Stick {
+    // Amount an axis has to move before it counts as a press
+    const THRESHOLD: f32 = 0.5;
+
+    /// Update the stick of gamepad `id`, returns the d-pad buttons which were pressed or released.
+    pub fn axis(&mut self, id: GamepadId, axis: Axis, value: f32) -> Vec<(Button, bool)> {
+        // Positive values point up or to the right
+        let (negative, positive) = match axis {
+            Axis::LeftStickX | Axis::DPadX => (Button::DPadLeft, Button::DPadRight),
+            Axis::LeftStickY | Axis::DPadY => (Button::DPadDown, Button::DPadUp),
+            _ => return Vec::new(),
+        };
+
+        let mut changes = Vec::new();
+        for (btn, pressed) in [
+            (negative, value <= -Stick::THRESHOLD),
+            (positive, value >= Stick::THRESHOLD),
+        ] {
+            let changed = if pressed {
+                self.pressed.insert((id, btn))
+            } else {
+                self.pressed.remove(&(id, btn))
+            };
+            if changed {
+                changes.push((btn, pressed));
+            }
+        }
+        changes
+    }
+
+    /// Amount of gamepads whose stick presses `btn`.
+    pub fn count(&self, btn: Button) -> usize {
+        self.pressed.iter().filter(|(_, b)| *b == btn).count()
+    }
+}
+
+// ----- //
+// Names //
+// ----- //
+
+#[rustfmt::skip]
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("a", KeyCode::A), ("b", KeyCode::B), ("c", KeyCode::C), ("d", KeyCode::D),
+    ("e", KeyCode::E), ("f", KeyCode::F), ("g", KeyCode::G), ("h", KeyCode::H),
+    ("i", KeyCode::I), ("j", KeyCode::J), ("k", KeyCode::K), ("l", KeyCode::L),
+    ("m", KeyCode::M), ("n", KeyCode::N), ("o", KeyCode::O), ("p", KeyCode::P),
+    ("q", KeyCode::Q), ("r", KeyCode::R), ("s", KeyCode::S), ("t", KeyCode::T),
+    ("u", KeyCode::U), ("v", KeyCode::V), ("w", KeyCode::W), ("x", KeyCode::X),
+    ("y", KeyCode::Y), ("z", KeyCode::Z),
+    ("0", KeyCode::Key0), ("1", KeyCode::Key1), ("2", KeyCode::Key2), ("3", KeyCode::Key3),
+    ("4", KeyCode::Key4), ("5", KeyCode::Key5), ("6", KeyCode::Key6), ("7", KeyCode::Key7),
+    ("8", KeyCode::Key8), ("9", KeyCode::Key9),
+    ("numpad0", KeyCode::Numpad0), ("numpad1", KeyCode::Numpad1),
+    ("numpad2", KeyCode::Numpad2), ("numpad3", KeyCode::Numpad3),
+    ("numpad4", KeyCode::Numpad4), ("numpad5", KeyCode::Numpad5),
+    ("numpad6", KeyCode::Numpad6), ("numpad7", KeyCode::Numpad7),
+    ("numpad8", KeyCode::Numpad8), ("numpad9", KeyCode::Numpad9),
+    ("f1", KeyCode::F1), ("f2", KeyCode::F2), ("f3", KeyCode::F3), ("f4", KeyCode::F4),
+    ("f5", KeyCode::F5), ("f6", KeyCode::F6), ("f7", KeyCode::F7), ("f8", KeyCode::F8),
+    ("f9", KeyCode::F9), ("f10", KeyCode::F10), ("f11", KeyCode::F11), ("f12", KeyCode::F12),
+    ("up", KeyCode::Up), ("down", KeyCode::Down),
+    ("left", KeyCode::Left), ("right", KeyCode::Right),
+    ("space", KeyCode::Space), ("return", KeyCode::Return), ("tab", KeyCode::Tab),
+    ("escape", KeyCode::Escape), ("backspace", KeyCode::Back),
+    ("insert", KeyCode::Insert), ("delete", KeyCode::Delete),
+    ("home", KeyCode::Home), ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp), ("pagedown", KeyCode::PageDown),
+    ("comma", KeyCode::Comma), ("period", KeyCode::Period), ("slash", KeyCode::Slash),
+    ("semicolon", KeyCode::Semicolon), ("apostrophe", KeyCode::Apostrophe),
+    ("minus", KeyCode::Minus), ("equals", KeyCode::Equals),
+    ("lbracket", KeyCode::LBracket), ("rbracket", KeyCode::RBracket),
+    ("backslash", KeyCode::Backslash), ("grave", KeyCode::Grave),
+];
+
+#[rustfmt::skip]
+const BUTTON_NAMES: &[(&str, Button)] = &[
+    ("up", Button::DPadUp), ("down", Button::DPadDown),
+    ("left", Button::DPadLeft), ("right", Button::DPadRight),
+    ("south", Button::South), ("east", Button::East),
+    ("north", Button::North), ("west", Button::West),
+    ("c", Button::C), ("z", Button::Z),
+    ("l1", Button::LeftTrigger), ("l2", Button::LeftTrigger2),
+    ("r1", Button::RightTrigger), ("r2", Button::RightTrigger2),
+    ("l3", Button::LeftThumb), ("r3", Button::RightThumb),
+    ("select", Button::Select), ("start", Button::Start), ("mode", Button::Mode),
+];
+
+// Find a key or button by its name, names are case insensitive
+fn from_name<K: Copy>(names: &[(&str, K)], name: &str) -> Option<K> {
+    let name = name.to_ascii_lowercase();
+    names.iter().find(|(n, _)| *n == name).map(|(_, key)| *key)
+}
+
+fn to_name<K: PartialEq>(names: &[(&'static str, K)], key: K) -> &'static str {
+    names
+        .iter()
+        .find(|(_, k)| *k == key)
+        .map_or("?", |(name, _)| name)
+}
@@ -1,22 +1,51 @@
 // Emulator-agnostic modules
+pub mod audio;
 mod debug_view;
+pub mod debugger;
+pub mod framebuffer;
+pub mod headless;
+pub mod input;
+mod keys;
 mod logger;
+pub mod movie;
+mod profile;
+mod render;
+mod rewind;
+pub mod savestate;
+mod screenshot;
 mod utils;
+mod video;
 
 // Emulators
 #[cfg(feature = "chip8")]
-mod chip8;
+pub mod chip8;
 
+use ggez::event::{Axis, Button, GamepadId};
 use ggez::{conf::*, input::keyboard::*, *};
 use log::*;
 
-use clap::{Parser, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
 
+use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
 use std::fs;
+use std::path::PathBuf;
 
+use audio::Player;
 use debug_view::{Debug, DebugView};
+use debugger::{Debugger, Watchpoint};
+use framebuffer::Framebuffer;
+use headless::Limit;
+use input::Input;
+use keys::{Binding, Bindings, Control, Stick};
+use movie::{Movie, Playback, Recorder};
+use profile::{Colour, Profile};
+use render::{Persistence, PixelStyle};
+use rewind::Rewind;
+use savestate::StateError;
+use video::Video;
 
 // ------------- //
 // Configuration //
@@ -24,37 +53,263 @@ use debug_view::{Debug, DebugView};
 
 #[derive(Parser)]
 #[structopt(name = "memu")]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 pub struct Conf {
     /// Show the current state of the emulator in the console
-    #[arg(short = 'D', long)]
+    #[arg(short = 'D', long, conflicts_with = "headless")]
     debug_view: bool,
+    /// Run without opening a window, stops after `--cycles` or `--frames`
+    #[arg(long, requires = "limit")]
+    headless: bool,
+    /// Amount of cycles to emulate in headless mode
+    #[arg(long, group = "limit", requires = "headless")]
+    cycles: Option<u64>,
+    /// Amount of 60Hz frames to emulate in headless mode
+    #[arg(long, group = "limit", requires = "headless")]
+    frames: Option<u64>,
+    /// Write the audio produced in headless mode to a WAV file
+    #[arg(long, requires = "headless")]
+    wav: Option<PathBuf>,
+    /// Write the screen to a PNG file at the end of headless mode
+    #[arg(long, requires = "headless")]
+    screenshot: Option<PathBuf>,
+    /// Size of a pixel of the emulator in screenshots, in pixels of the image
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    screenshot_scale: u32,
+    /// Record the screen to an animated GIF (`.gif`) or a raw video stream (`.y4m`)
+    #[arg(long, value_name = "PATH")]
+    video: Option<PathBuf>,
+    /// Size of a pixel of the emulator in videos, in pixels of the video
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    video_scale: u32,
+    /// Amount of frames that can be rewound by holding `<backspace>`, 0 disables rewinding
+    #[arg(long, default_value = "600")]
+    rewind: usize,
     #[arg(
         short, long, hide_default_value = true, value_enum,
         default_value="warn", default_value_if("debug-view", "true", "trace"),
     )]
     /// The log level to use. Defaults to `trace` if `--debug_view` is set, or `warn` otherwise
     log_level: LevelFilter,
+    /// Pause emulation before the instruction at this (hexadecimal) address is executed, can be
+    /// passed multiple times
+    #[arg(long = "break", value_name = "ADDR", value_parser = debugger::parse_address)]
+    breakpoints: Vec<u16>,
+    /// Pause emulation when memory or a register is accessed, e.g. `300-30F:w:==10` or
+    /// `vf:changed`, can be passed multiple times. See the README for the full syntax
+    #[arg(long = "watch", value_name = "WATCHPOINT")]
+    watchpoints: Vec<Watchpoint>,
+    /// Chip8 quirks to use, defaults to the quirks of the chosen chip8 variant
+    #[arg(long, value_enum)]
+    quirks: Option<QuirksPreset>,
+    /// Amount of instructions to execute per 60Hz frame
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    cycles_per_frame: Option<u32>,
+    /// Amount of instructions to execute per second, the timers keep running at 60Hz
+    #[arg(long, conflicts_with = "cycles_per_frame", value_parser = clap::value_parser!(u32).range(1..))]
+    ips: Option<u32>,
+    /// Execute exactly the same amount of instructions every 60Hz frame, rounding the speed to
+    /// whole instructions per frame, so runs are identical on every machine
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    frame_locked: Option<bool>,
+    /// Random number generator used by chip8 `Cxkk` instructions
+    #[arg(long, value_enum)]
+    random: Option<RandomMode>,
+    /// Named palette to draw with
+    #[arg(long, value_enum)]
+    palette: Option<PalettePreset>,
+    /// Colours to draw with, as `#RRGGBB`: background, foreground and additional bitplanes.
+    /// Replaces the first colours of the palette
+    #[arg(long, value_delimiter = ',')]
+    colours: Option<Vec<Colour>>,
+    /// Look of the pixels in the window and in screenshots
+    #[arg(long, value_enum, default_value = "plain")]
+    pixel_style: PixelStyle,
+    /// Fade out cleared pixels to reduce flicker, keeping this fraction (0 up to 1) of their
+    /// brightness every 60Hz frame
+    #[arg(long, value_name = "DECAY", value_parser = render::parse_decay)]
+    persistence: Option<f32>,
+    /// Map a key onto a key of the emulator or a control, e.g. `--key up=5` or `--key p=step`,
+    /// can be passed multiple times. See the README for the names of keys and controls
+    #[arg(long = "key", value_name = "KEY=BINDING", value_parser = keys::parse_key_binding)]
+    keys: Vec<(String, Binding)>,
+    /// Map a gamepad button onto a key of the emulator or a control, e.g. `--button south=5`,
+    /// can be passed multiple times
+    #[arg(long = "button", value_name = "BUTTON=BINDING", value_parser = keys::parse_key_binding)]
+    buttons: Vec<(String, Binding)>,
+    /// Print the key bindings used for the rom and exit
+    #[arg(long)]
+    print_keymap: bool,
+    /// Record the keypad inputs to a movie file, which can be replayed with `--play`
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["play", "headless"])]
+    record: Option<PathBuf>,
+    /// Replay the inputs of a movie recorded with `--record`
+    #[arg(long, value_name = "PATH")]
+    play: Option<PathBuf>,
+    /// Seed of the random number generator, random by default. Movies store their own seed
+    #[arg(long, conflicts_with = "play")]
+    seed: Option<u64>,
+    /// Rom database to use instead of the one in the configuration directory of the user
+    #[arg(long)]
+    rom_db: Option<PathBuf>,
+    #[arg(value_enum, required = true)]
+    /// Emulator to use, `auto` picks the emulator from the rom database
+    emulator: Option<EmulatorKind>,
+    /// Path to the rom to emulate
+    #[arg(required = true)]
+    rom_path: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write a listing of a chip8 rom
+    Disasm(DisasmConf),
+    /// Assemble a chip8 program written in the classic syntax of `disasm` into a rom
+    Asm(AsmConf),
+}
+
+#[derive(Args)]
+struct DisasmConf {
+    /// Notation of the instructions
+    #[arg(long, value_enum, default_value = "octo")]
+    syntax: DisasmSyntax,
+    /// File to write the listing to, instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Chip8 variant the rom was written for, `auto` picks it from the rom database
     #[arg(value_enum)]
-    /// Emulator to use
     emulator: EmulatorKind,
-    /// Path to the rom to emulate
+    /// Path to the rom to disassemble
     rom_path: String,
 }
 
+#[derive(Args)]
+struct AsmConf {
+    /// File to write the rom to, defaults to the source path with the `ch8` extension
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Path to the program to assemble
+    source_path: PathBuf,
+}
+
 #[derive(ValueEnum, Debug, Clone, Copy)]
+enum DisasmSyntax {
+    /// Octo assembly language
+    Octo,
+    /// Mnemonics of Cowgod's Chip-8 technical reference
+    Classic,
+}
+
+impl Conf {
+    // Both are required by clap, unless a subcommand is used
+    fn emulator(&self) -> EmulatorKind {
+        self.emulator.unwrap_or(EmulatorKind::Auto)
+    }
+
+    fn rom_path(&self) -> &str {
+        self.rom_path.as_deref().unwrap_or_default()
+    }
+
+    fn headless_limit(&self) -> Option<Limit> {
+        if !self.headless {
+            return None;
+        }
+        self.cycles
+            .map(Limit::Cycles)
+            .or_else(|| self.frames.map(Limit::Frames))
+    }
+
+    /// Settings passed on the command line, which override the profile of the rom.
+    fn profile(&self) -> Profile {
+        let platform = match self.emulator() {
+            EmulatorKind::Auto => None,
+            kind => Some(kind),
+        };
+
+        Profile {
+            name: None,
+            platform,
+            quirks: self.quirks,
+            cycles_per_frame: self.cycles_per_frame,
+            ips: self.ips,
+            frame_locked: self.frame_locked,
+            random: self.random,
+            keys: self.keys.iter().cloned().collect(),
+            buttons: self.buttons.iter().cloned().collect(),
+            palette: self.palette,
+            colours: self.colours.clone(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
 pub enum EmulatorKind {
+    #[serde(skip)]
+    Auto,
     Chip8,
+    #[value(name = "schip")]
+    #[serde(rename = "schip")]
+    SuperChip,
+    #[value(name = "xochip")]
+    #[serde(rename = "xochip")]
+    XoChip,
 }
 
 impl fmt::Display for EmulatorKind {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         let name = match self {
+            EmulatorKind::Auto => "Automatic",
             EmulatorKind::Chip8 => "Chip 8",
+            EmulatorKind::SuperChip => "Super Chip 8",
+            EmulatorKind::XoChip => "XO-Chip",
         };
         write!(fmt, "{}", name)
     }
 }
 
+/// Named sets of chip8 quirks, after the interpreters which introduced them.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum QuirksPreset {
+    /// COSMAC VIP
+    Vip,
+    /// CHIP-48
+    Chip48,
+    /// Super Chip8 1.1
+    Schip,
+    /// XO-Chip
+    Xochip,
+}
+
+/// Random number generators of the chip8 `Cxkk` instruction.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RandomMode {
+    /// ChaCha8 generator seeded with `--seed`
+    Chacha,
+    /// Modelled on the routine of the COSMAC VIP interpreter
+    Vip,
+}
+
+/// Named colour palettes.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PalettePreset {
+    /// White on black
+    Mono,
+    /// Green phosphor monitor
+    Green,
+    /// Amber phosphor monitor
+    Amber,
+    /// Green liquid crystal display
+    Lcd,
+    /// Default colours of the Octo IDE
+    Octo,
+}
+
 // ------ //
 // Errors //
 // ------ //
@@ -78,6 +333,9 @@ impl Error for MissingFeatureError {
 // Emulator Trait //
 // -------------- //
 
+/// Length of a 60Hz frame (16.6 ms), at which displays refresh and emulated timers tick.
+pub const FRAME_TIME: std::time::Duration = std::time::Duration::from_nanos(16666666);
+
 pub trait Emulator: Debug {
     /// Load a rom into the memory of the emulator
     fn load_rom(&mut self, rom: Vec<u8>);
@@ -85,20 +343,50 @@ pub trait Emulator: Debug {
     /// Advance the emulator by the amount of cycles that should have occured in the elapsed time
     fn advance(&mut self, elapsed: std::time::Duration) -> bool;
 
+    /// Execute exactly `cycles` cycles, unless a breakpoint or watchpoint is hit
+    fn run(&mut self, cycles: u64) -> bool;
+
+    /// Amount of cycles executed since the rom was loaded
+    fn cycles(&self) -> u64;
+
     /// Amount of time that needs to pass for a single cycle
     fn cycle_dt(&self) -> std::time::Duration;
 
+    /// Change the amount of time a single cycle takes, timers keep running at their own rate
+    fn set_cycle_dt(&mut self, dt: std::time::Duration);
+
+    /// Reseed the random number generator, so runs can be reproduced
+    fn seed(&mut self, seed: u64);
+
+    /// Take the audio samples produced since the previous call, at `audio::SAMPLE_RATE`
+    fn audio(&mut self) -> Vec<i16>;
+
     /// Handle a down event
-    fn key_down(&mut self, key: KeyCode);
+    fn key_down(&mut self, input: Input);
 
     /// Handle a key_up event
-    fn key_up(&mut self, key: KeyCode);
+    fn key_up(&mut self, input: Input);
 
     /// Size of the drawn area
     fn draw_size(&self) -> (f32, f32);
 
-    /// Draw the emulator state to the screen
-    fn draw(&self, ctx: &mut Context) -> GameResult<()>;
+    /// Current contents of the screen of the emulator
+    fn framebuffer(&self) -> Framebuffer<'_>;
+
+    /// Serialize the complete state of the emulator
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Restore a state created by `save_state`, leaves the emulator untouched on failure
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError>;
+
+    /// Address of the next instruction to execute
+    fn pc(&self) -> u16;
+
+    /// Breakpoints, watchpoints and debug view cursor of the emulator
+    fn debugger(&self) -> &Debugger;
+
+    /// Breakpoints, watchpoints and debug view cursor of the emulator, which can be modified
+    fn debugger_mut(&mut self) -> &mut Debugger;
 }
 
 // ----------------------------- //
@@ -106,24 +394,239 @@ pub trait Emulator: Debug {
 // ----------------------------- //
 
 #[cfg(feature = "chip8")]
-fn init_chip8(_: EmulatorKind) -> Result<Box<dyn Emulator>, Box<dyn Error>> {
-    Ok(Box::new(chip8::Chip8::new()))
+fn chip8_platform(kind: EmulatorKind) -> chip8::Platform {
+    match kind {
+        EmulatorKind::SuperChip => chip8::Platform::SuperChip,
+        EmulatorKind::XoChip => chip8::Platform::XoChip,
+        _ => chip8::Platform::Chip8,
+    }
+}
+
+#[cfg(feature = "chip8")]
+fn init_chip8(kind: EmulatorKind, profile: &Profile) -> Result<Box<dyn Emulator>, Box<dyn Error>> {
+    use chip8::Quirks;
+
+    let platform = chip8_platform(kind);
+    let quirks = match profile.quirks {
+        Some(QuirksPreset::Vip) => Quirks::vip(),
+        Some(QuirksPreset::Chip48) => Quirks::chip48(),
+        Some(QuirksPreset::Schip) => Quirks::schip(),
+        Some(QuirksPreset::Xochip) => Quirks::xochip(),
+        None => platform.default_quirks(),
+    };
+
+    let mut emulator = chip8::Chip8::new(platform, quirks);
+    emulator.set_frame_locked(profile.frame_locked.unwrap_or(false));
+    emulator.set_vip_random(matches!(profile.random, Some(RandomMode::Vip)));
+    if let Some(ips) = profile.ips {
+        emulator.set_instructions_per_second(ips);
+    } else if let Some(cycles) = profile.cycles_per_frame {
+        emulator.set_cycles_per_frame(cycles);
+    }
+    Ok(Box::new(emulator))
 }
 #[cfg(not(feature = "chip8"))]
-fn init_chip8(kind: EmulatorKind) -> Result<Box<dyn Emulator>, Box<dyn Error>> {
+fn init_chip8(kind: EmulatorKind, _: &Profile) -> Result<Box<dyn Emulator>, Box<dyn Error>> {
     Err(Box::new(MissingFeatureError(kind.to_string())))
 }
 
-fn init_emulator(conf: &Conf) -> Result<Box<dyn Emulator>, Box<dyn Error>> {
-    let mut emulator = match conf.emulator {
-        EmulatorKind::Chip8 => init_chip8(conf.emulator)?,
+/// Create the emulator and load the rom into it.
+///
+/// The rom is looked up in the rom database, settings passed on the command line override the
+/// ones found there. Returns the emulator together with the profile it was created with, the
+/// platform of this profile is always set.
+fn init_emulator(conf: &Conf) -> Result<(Box<dyn Emulator>, Profile), Box<dyn Error>> {
+    info!("Loading rom: `{}`", conf.rom_path());
+    let rom = fs::read(conf.rom_path())?;
+
+    let profile = profile::lookup(&rom, conf.rom_db.as_deref(), conf.profile())?;
+    // The lookup falls back to chip8 when the platform is not known
+    let kind = profile.platform.unwrap_or(EmulatorKind::Chip8);
+
+    let mut emulator = match kind {
+        // `Auto` does not make it past the profile lookup, which falls back to chip8
+        EmulatorKind::Auto
+        | EmulatorKind::Chip8
+        | EmulatorKind::SuperChip
+        | EmulatorKind::XoChip => init_chip8(kind, &profile)?,
+    };
+    emulator.load_rom(rom);
+
+    Ok((emulator, profile))
+}
+
+/// Start recording or playing the movie passed on the command line, and seed the emulator.
+///
+/// A recorded movie stores the seed, which is reused when it is played, and the settings of the
+/// emulator, which have to be the same when it is played.
+fn init_movie(
+    conf: &Conf,
+    emulator: &mut dyn Emulator,
+    profile: &Profile,
+) -> Result<Option<Movie>, Box<dyn Error>> {
+    let rom_hash = || fs::read(conf.rom_path()).map(|rom| profile::hash(&rom));
+    let mut seed = conf.seed.unwrap_or_else(rand::random);
+    let settings = movie_settings(emulator, profile);
+
+    let movie = if let Some(path) = &conf.record {
+        info!("Recording movie to `{}`", path.display());
+        let recorder = Recorder::create(path, &rom_hash()?, seed, &settings)?;
+        Some(Movie::Record(recorder))
+    } else if let Some(path) = &conf.play {
+        let playback = Playback::read(path)?;
+        if playback.rom() != rom_hash()? {
+            warn!("Movie `{}` was recorded with another rom", path.display());
+        }
+        playback.check(&settings)?;
+        info!("Playing movie `{}`", path.display());
+        seed = playback.seed();
+        Some(Movie::Play(playback))
+    } else {
+        None
     };
 
-    info!("Loading rom: `{}`", &conf.rom_path);
+    info!("Seeding the random number generator with {}", seed);
+    emulator.seed(seed);
+    Ok(movie)
+}
+
+fn movie_settings(emulator: &dyn Emulator, profile: &Profile) -> movie::Settings {
+    let name = |value: Option<clap::builder::PossibleValue>| {
+        value.map_or_else(|| String::from("default"), |v| v.get_name().to_string())
+    };
+
+    movie::Settings {
+        platform: name(profile.platform.and_then(|p| p.to_possible_value())),
+        quirks: name(profile.quirks.and_then(|q| q.to_possible_value())),
+        cycle_dt: emulator.cycle_dt(),
+        frame_locked: profile.frame_locked.unwrap_or(false),
+        random: name(profile.random.and_then(|r| r.to_possible_value())),
+    }
+}
+
+/// Start recording the video passed on the command line.
+fn init_video(
+    conf: &Conf,
+    emulator: &dyn Emulator,
+    profile: &Profile,
+) -> Result<Option<Video>, Box<dyn Error>> {
+    let path = match &conf.video {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    info!("Recording video to `{}`", path.display());
+    let palette = palette(profile);
+    let video = Video::create(path, emulator, &palette, conf.video_scale as usize)?;
+    Ok(Some(video))
+}
+
+// ----------- //
+// Subcommands //
+// ----------- //
+
+#[cfg(feature = "chip8")]
+fn disasm(conf: &DisasmConf) -> Result<(), Box<dyn Error>> {
+    use chip8::disasm::{self, Syntax};
+
     let rom = fs::read(&conf.rom_path)?;
-    emulator.load_rom(rom);
+    let kind = match conf.emulator {
+        EmulatorKind::Auto => profile::lookup(&rom, None, Profile::default())?.platform,
+        kind => Some(kind),
+    };
+    let syntax = match conf.syntax {
+        DisasmSyntax::Octo => Syntax::Octo,
+        DisasmSyntax::Classic => Syntax::Classic,
+    };
+
+    let platform = chip8_platform(kind.unwrap_or(EmulatorKind::Chip8));
+    let listing = disasm::disassemble(&rom, platform, syntax);
+
+    match &conf.output {
+        Some(path) => fs::write(path, listing)?,
+        None => print!("{}", listing),
+    }
+    Ok(())
+}
+#[cfg(not(feature = "chip8"))]
+fn disasm(conf: &DisasmConf) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(MissingFeatureError(conf.emulator.to_string())))
+}
+
+#[cfg(feature = "chip8")]
+fn asm(conf: &AsmConf) -> Result<(), Box<dyn Error>> {
+    let source = fs::read_to_string(&conf.source_path)?;
+    let rom = chip8::asm::assemble(&source)?;
 
-    Ok(emulator)
+    let output = match &conf.output {
+        Some(path) => path.clone(),
+        None => conf.source_path.with_extension("ch8"),
+    };
+    fs::write(&output, rom)?;
+    Ok(())
+}
+#[cfg(not(feature = "chip8"))]
+fn asm(_conf: &AsmConf) -> Result<(), Box<dyn Error>> {
+    Err(Box::new(MissingFeatureError(
+        EmulatorKind::Chip8.to_string(),
+    )))
+}
+
+// ------- //
+// Colours //
+// ------- //
+
+impl PalettePreset {
+    // Background, foreground and the colours used by additional bitplanes
+    fn colours(self) -> [Colour; 4] {
+        match self {
+            PalettePreset::Mono => [
+                Colour(0x00, 0x00, 0x00),
+                Colour(0xFF, 0xFF, 0xFF),
+                Colour(0xAA, 0xAA, 0xAA),
+                Colour(0x55, 0x55, 0x55),
+            ],
+            PalettePreset::Green => [
+                Colour(0x0A, 0x14, 0x0A),
+                Colour(0x33, 0xFF, 0x66),
+                Colour(0x1F, 0x99, 0x3D),
+                Colour(0x14, 0x66, 0x29),
+            ],
+            PalettePreset::Amber => [
+                Colour(0x14, 0x0C, 0x00),
+                Colour(0xFF, 0xB0, 0x00),
+                Colour(0xB3, 0x7B, 0x00),
+                Colour(0x66, 0x46, 0x00),
+            ],
+            PalettePreset::Lcd => [
+                Colour(0x9B, 0xBC, 0x0F),
+                Colour(0x0F, 0x38, 0x0F),
+                Colour(0x30, 0x62, 0x30),
+                Colour(0x8B, 0xAC, 0x0F),
+            ],
+            PalettePreset::Octo => [
+                Colour(0x99, 0x66, 0x00),
+                Colour(0xFF, 0xCC, 0x00),
+                Colour(0xFF, 0x66, 0x00),
+                Colour(0x66, 0x22, 0x00),
+            ],
+        }
+    }
+}
+
+/// Colours to draw with, colours set in the profile replace the ones of its palette.
+fn palette(profile: &Profile) -> Vec<Colour> {
+    let preset = profile.palette.unwrap_or(PalettePreset::Mono);
+    let mut palette = preset.colours().to_vec();
+
+    for (idx, colour) in profile.colours.iter().flatten().enumerate() {
+        match palette.get_mut(idx) {
+            Some(entry) => *entry = *colour,
+            None => palette.push(*colour),
+        }
+    }
+
+    palette
 }
 
 // ---------- //
@@ -161,27 +664,77 @@ struct State {
     emulator: Box<dyn Emulator>,
     debug_view: DebugView,
 
+    // Save states
+    emulator_kind: EmulatorKind,
+    rom_path: String,
+
+    // Input
+    bindings: Bindings,
+    stick: Stick,
+    // Gamepad buttons which are held down, on every gamepad
+    buttons: HashSet<(GamepadId, Button)>,
+
+    // Rewind
+    rewind: Rewind,
+
+    // Movies
+    movie: Option<Movie>,
+
+    // Video
+    video: Option<Video>,
+
+    // Audio
+    player: Player,
+
     // Emulation mode / speed
     progress_mode: ProgressMode,
     speed_factor: f32,
 
     // Drawing
+    palette: Vec<Colour>,
+    pixel_style: PixelStyle,
+    persistence: Option<Persistence>,
+    screenshot_scale: usize,
     should_draw: bool,
 }
 
 impl State {
-    fn new(conf: &Conf, emulator: Box<dyn Emulator>, debug_view: DebugView) -> State {
+    fn new(
+        conf: &Conf,
+        emulator: Box<dyn Emulator>,
+        profile: &Profile,
+        bindings: Bindings,
+        movie: Option<Movie>,
+        video: Option<Video>,
+        debug_view: DebugView,
+    ) -> State {
         let progress_mode = if conf.debug_view {
             ProgressMode::Cycle(false)
         } else {
             ProgressMode::Normal
         };
 
+        // Going back to an older state would desynchronise the movie
+        let rewind = if movie.is_some() { 0 } else { conf.rewind };
+
         State {
             emulator,
             debug_view,
+            emulator_kind: profile.platform.unwrap_or(EmulatorKind::Chip8),
+            rom_path: conf.rom_path().to_string(),
+            bindings,
+            stick: Stick::default(),
+            buttons: HashSet::new(),
+            rewind: Rewind::new(rewind),
+            movie,
+            video,
+            player: Player::new(),
             progress_mode,
             speed_factor: 1.0,
+            palette: palette(profile),
+            pixel_style: conf.pixel_style,
+            persistence: conf.persistence.map(Persistence::new),
+            screenshot_scale: conf.screenshot_scale as usize,
             should_draw: true,
         }
     }
@@ -228,6 +781,256 @@ impl State {
         self.speed_factor -= 0.1;
     }
 
+    /// Change the amount of instructions executed per frame by about a tenth, at least by one.
+    fn change_cpu_speed(&mut self, faster: bool) {
+        if self.movie.is_some() {
+            warn!("The CPU speed cannot be changed while a movie is recorded or played");
+            return;
+        }
+
+        let cycles = (FRAME_TIME.as_secs_f64() / self.emulator.cycle_dt().as_secs_f64()).round();
+        let step = (cycles / 10.0).round().max(1.0);
+        let cycles = if faster { cycles + step } else { cycles - step }.max(1.0);
+
+        self.emulator.set_cycle_dt(FRAME_TIME.div_f64(cycles));
+        info!(
+            "CPU speed changed to {} instructions per frame ({} per second)",
+            cycles,
+            cycles * 60.0
+        );
+        self.force_draw();
+    }
+
+    fn state_path(&self, slot: u8) -> String {
+        format!("{}.state{}", self.rom_path, slot)
+    }
+
+    fn save_state(&mut self, slot: u8) {
+        let path = self.state_path(slot);
+        let kind = format!("{:?}", self.emulator_kind);
+
+        match savestate::write_file(&path, &kind, &self.emulator.save_state()) {
+            Ok(()) => info!("Saved state to slot {} (`{}`)", slot, path),
+            Err(e) => error!("Failed to save state to `{}`: {}", path, e),
+        }
+    }
+
+    fn load_state(&mut self, slot: u8) {
+        if self.movie.is_some() {
+            warn!("States cannot be loaded while a movie is recorded or played");
+            return;
+        }
+
+        let path = self.state_path(slot);
+        let kind = format!("{:?}", self.emulator_kind);
+
+        let res = savestate::read_file(&path, &kind)
+            .and_then(|state| Ok(self.emulator.load_state(&state)?));
+
+        match res {
+            Ok(()) => {
+                info!("Loaded state from slot {} (`{}`)", slot, path);
+                self.force_draw();
+            }
+            Err(e) => error!("Failed to load state from `{}`: {}", path, e),
+        }
+    }
+
+    fn record_rewind(&mut self) {
+        let progressed = match self.progress_mode {
+            ProgressMode::Normal => true,
+            ProgressMode::Cycle(step) | ProgressMode::Frame(step) => step,
+        };
+
+        if progressed {
+            self.rewind.push(self.emulator.save_state());
+        }
+    }
+
+    /// Colours of the pixels of the emulator, as they are shown.
+    fn colours(&self) -> Vec<Colour> {
+        match &self.persistence {
+            Some(persistence) => persistence.colours(),
+            None => render::colours(self.emulator.framebuffer(), &self.palette),
+        }
+    }
+
+    fn screenshot(&self) {
+        let path = screenshot::next_path(&self.rom_path);
+        let colours = self.colours();
+        let width = self.emulator.framebuffer().width;
+        let (scale, style) = (self.screenshot_scale, self.pixel_style);
+
+        match screenshot::write_png(&path, &colours, width, scale, style) {
+            Ok(()) => info!("Saved screenshot to `{}`", path.display()),
+            Err(e) => error!("Failed to save screenshot to `{}`: {}", path.display(), e),
+        }
+    }
+
+    /// Perform the action of a control key or button, `shift` selects its alternative action.
+    fn control(&mut self, ctx: &mut Context, control: Control, shift: bool) {
+        match control {
+            Control::Quit => event::quit(ctx),
+            // Speed / Cycle control
+            Control::Mode => self.change_progress_mode(ctx),
+            Control::Faster => self.inc_speed(ctx),
+            Control::Slower => self.dec_speed(ctx),
+            Control::CpuFaster => self.change_cpu_speed(true),
+            Control::CpuSlower => self.change_cpu_speed(false),
+            Control::Step => match self.progress_mode {
+                ProgressMode::Cycle(false) => self.set_progress(),
+                ProgressMode::Frame(false) => self.set_progress(),
+                _ => (),
+            },
+            // Checked every update, as it is held down
+            Control::Rewind => (),
+            // Checked when another control is used
+            Control::Shift => (),
+            // Screenshots
+            Control::Screenshot => self.screenshot(),
+            // Breakpoints
+            Control::Breakpoint if shift => self.clear_breakpoints(),
+            Control::Breakpoint => self.toggle_breakpoint(),
+            Control::CursorUp => self.move_cursor(-1),
+            Control::CursorDown => self.move_cursor(1),
+            // Save states
+            Control::State(slot) if shift => self.save_state(slot),
+            Control::State(slot) => self.load_state(slot),
+        }
+    }
+
+    /// Pass the screen to the video that is recorded, which keeps it if the emulator redrew it.
+    fn record_video(&mut self, redraw: bool) {
+        if let Some(video) = &mut self.video {
+            if let Err(e) = video.update(self.emulator.as_ref(), redraw) {
+                error!("Failed to record video, recording stopped: {}", e);
+                self.video = None;
+            }
+        }
+    }
+
+    /// Toggle a breakpoint on the instruction selected in the debug view, which is the next
+    /// instruction unless the cursor was moved.
+    fn toggle_breakpoint(&mut self) {
+        let pc = self.emulator.pc();
+        let debugger = self.emulator.debugger_mut();
+        let addr = debugger.cursor().unwrap_or(pc);
+        if debugger.toggle_breakpoint(addr) {
+            if addr == pc {
+                // The emulator is already at the breakpoint, stepping executes its instruction
+                debugger.resume(pc);
+            }
+            info!("Breakpoint set at {:#06X}", addr);
+        } else {
+            info!("Breakpoint cleared at {:#06X}", addr);
+        }
+        self.log_breakpoints();
+    }
+
+    fn move_cursor(&mut self, instructions: i16) {
+        let pc = self.emulator.pc();
+        self.emulator.debugger_mut().move_cursor(pc, instructions);
+    }
+
+    fn clear_breakpoints(&mut self) {
+        self.emulator.debugger_mut().clear_breakpoints();
+        info!("Cleared all breakpoints");
+    }
+
+    fn log_breakpoints(&self) {
+        let addrs: Vec<String> = self
+            .emulator
+            .debugger()
+            .breakpoints()
+            .map(|addr| format!("{:#06X}", addr))
+            .collect();
+        info!("Breakpoints: [{}]", addrs.join(", "));
+    }
+
+    /// Pause the emulation when a breakpoint or watchpoint was hit.
+    fn check_hit(&mut self) {
+        if let Some(hit) = self.emulator.debugger_mut().take_hit() {
+            info!("{}", hit);
+            self.emulator.debugger_mut().reset_cursor();
+            self.progress_mode = ProgressMode::Cycle(false);
+        }
+    }
+
+    /// Advance the emulator, feeding it the inputs of the movie that is played.
+    fn advance(&mut self, elapsed: std::time::Duration) -> bool {
+        match &mut self.movie {
+            Some(Movie::Play(playback)) => playback.advance(self.emulator.as_mut(), elapsed),
+            _ => self.emulator.advance(elapsed),
+        }
+    }
+
+    /// Pass a key event to the emulator, unless it is controlled by a movie.
+    fn input(&mut self, input: Input, pressed: bool) {
+        match &mut self.movie {
+            Some(Movie::Play(playback)) if !playback.finished() => return,
+            Some(Movie::Record(recorder)) => {
+                recorder.record(self.emulator.cycles(), input, pressed)
+            }
+            _ => (),
+        }
+
+        if pressed {
+            self.emulator.key_down(input);
+        } else {
+            self.emulator.key_up(input);
+        }
+    }
+
+    /// Whether a key or button bound to `control` is held down.
+    fn control_held(&self, ctx: &Context, control: Control) -> bool {
+        let control = Binding::Control(control);
+        let key_held = self
+            .bindings
+            .keys
+            .iter()
+            .any(|(key, binding)| *binding == control && utils::is_key_pressed(ctx, *key));
+        let button_held = self
+            .bindings
+            .buttons
+            .iter()
+            .any(|(btn, binding)| *binding == control && self.button_holders(*btn) > 0);
+        key_held || button_held
+    }
+
+    /// Amount of gamepad buttons and sticks which hold `btn` down.
+    fn button_holders(&self, btn: Button) -> usize {
+        let buttons = self.buttons.iter().filter(|(_, b)| *b == btn).count();
+        buttons + self.stick.count(btn)
+    }
+
+    /// Trigger the binding of a button which was pressed or released by a gamepad or a stick.
+    ///
+    /// The binding is only pressed by the first holder of the button, and released by the last.
+    fn gamepad_button(&mut self, ctx: &mut Context, btn: Button, pressed: bool) {
+        let holders = self.button_holders(btn);
+        match (self.bindings.buttons.get(&btn).copied(), holders) {
+            (Some(Binding::Input(input)), 1) if pressed => self.input(input, true),
+            (Some(Binding::Input(input)), 0) => self.input(input, false),
+            (Some(Binding::Control(control)), 1) if pressed => {
+                let shift = self.control_held(ctx, Control::Shift);
+                self.control(ctx, control, shift);
+            }
+            _ => (),
+        }
+    }
+
+    fn rewind(&mut self) -> bool {
+        match self.rewind.pop() {
+            Some(state) => {
+                if let Err(e) = self.emulator.load_state(state) {
+                    error!("Failed to rewind: {}", e);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
     fn set_progress(&mut self) {
         self.progress_mode = match self.progress_mode {
             ProgressMode::Cycle(_) => ProgressMode::Cycle(true),
@@ -253,7 +1056,11 @@ impl State {
 
         while !frame && ctr < MAX_CYCLES {
             ctr += 1;
-            frame = self.emulator.advance(dt);
+            frame = self.advance(dt);
+
+            if self.emulator.debugger().hit().is_some() {
+                return frame;
+            }
         }
 
         if !frame {
@@ -273,16 +1080,29 @@ impl State {
 
 impl ggez::event::EventHandler for State {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let rewinding = self.control_held(ctx, Control::Rewind);
+
+        if !rewinding {
+            self.record_rewind();
+        }
+
         let emu_requires_draw = match self.progress_mode {
-            ProgressMode::Normal => self
-                .emulator
-                .advance(timer::delta(ctx).mul_f32(self.speed_factor)),
-            ProgressMode::Cycle(true) => self.emulator.advance(self.emulator.cycle_dt()),
+            _ if rewinding => self.rewind(),
+            ProgressMode::Normal => self.advance(timer::delta(ctx).mul_f32(self.speed_factor)),
+            ProgressMode::Cycle(true) => self.advance(self.emulator.cycle_dt()),
             ProgressMode::Frame(true) => self.frame_mode_cycle(),
             _ => false,
         };
 
         self.clear_progress();
+        self.record_video(emu_requires_draw);
+        if let Some(persistence) = &mut self.persistence {
+            // Fading pixels have to be redrawn, even when the emulator did not draw
+            let changed = persistence.update(self.emulator.as_ref(), &self.palette);
+            self.maybe_draw(changed);
+        }
+        self.check_hit();
+        self.player.play(self.emulator.audio());
         self.maybe_draw(emu_requires_draw);
         self.debug_view.draw(&self.emulator).unwrap();
 
@@ -293,8 +1113,10 @@ impl ggez::event::EventHandler for State {
         if self.should_draw {
             self.clear_draw();
 
-            graphics::clear(ctx, graphics::BLACK);
-            self.emulator.draw(ctx)?;
+            let Colour(r, g, b) = self.palette[0];
+            graphics::clear(ctx, graphics::Color::from_rgb(r, g, b));
+            let width = self.emulator.framebuffer().width;
+            draw_framebuffer(ctx, &self.colours(), width, self.pixel_style)?;
             graphics::present(ctx)?;
         }
 
@@ -307,24 +1129,73 @@ impl ggez::event::EventHandler for State {
     }
 
     fn key_up_event(&mut self, _ctx: &mut Context, code: KeyCode, _mods: KeyMods) {
-        self.emulator.key_up(code);
+        if let Some(Binding::Input(input)) = self.bindings.keys.get(&code).copied() {
+            self.input(input, false);
+        }
     }
 
-    fn key_down_event(&mut self, ctx: &mut Context, code: KeyCode, _mods: KeyMods, _: bool) {
-        match code {
-            KeyCode::Escape => event::quit(ctx),
-            // Speed / Cycle control
-            KeyCode::Slash => self.change_progress_mode(ctx),
-            KeyCode::Period => self.inc_speed(ctx),
-            KeyCode::Comma => self.dec_speed(ctx),
-            KeyCode::Space => match self.progress_mode {
-                ProgressMode::Cycle(false) => self.set_progress(),
-                ProgressMode::Frame(false) => self.set_progress(),
-                _ => (),
-            },
-            key => self.emulator.key_down(key)
+    fn key_down_event(&mut self, ctx: &mut Context, code: KeyCode, mods: KeyMods, _: bool) {
+        let shift = mods.contains(KeyMods::SHIFT) || self.control_held(ctx, Control::Shift);
+
+        match self.bindings.keys.get(&code).copied() {
+            Some(Binding::Input(input)) => self.input(input, true),
+            Some(Binding::Control(control)) => self.control(ctx, control, shift),
+            Some(Binding::None) | None => (),
         }
     }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
+        if self.buttons.insert((id, btn)) {
+            self.gamepad_button(ctx, btn, true);
+        }
+    }
+
+    fn gamepad_button_up_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
+        if self.buttons.remove(&(id, btn)) {
+            self.gamepad_button(ctx, btn, false);
+        }
+    }
+
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        for (btn, pressed) in self.stick.axis(id, axis, value) {
+            self.gamepad_button(ctx, btn, pressed);
+        }
+    }
+}
+
+/// Draw the colours of a screen `width` pixels wide as a single texture, scaled without
+/// smoothing.
+fn draw_framebuffer(
+    ctx: &mut Context,
+    colours: &[Colour],
+    width: usize,
+    style: PixelStyle,
+) -> GameResult<()> {
+    let height = colours.len() / width;
+
+    // Plain pixels are scaled by the GPU, other styles are drawn at the size of the window
+    let scale = match style {
+        PixelStyle::Plain => 1,
+        _ => {
+            let (win_width, win_height) = graphics::drawable_size(ctx);
+            let width_fct = win_width as usize / width;
+            let height_fct = win_height as usize / height;
+            width_fct.min(height_fct).max(1)
+        }
+    };
+
+    let mut rgba = Vec::with_capacity(colours.len() * scale * scale * 4);
+    for Colour(r, g, b) in render::render(colours, width, scale, style) {
+        rgba.extend_from_slice(&[r, g, b, 0xFF]);
+    }
+
+    let (width, height) = ((width * scale) as u16, (height * scale) as u16);
+    let mut image = graphics::Image::from_rgba8(ctx, width, height, &rgba)?;
+    image.set_filter(graphics::FilterMode::Nearest);
+
+    let fct = 1.0 / scale as f32;
+    let param = graphics::DrawParam::default().scale([fct, fct]);
+    graphics::draw(ctx, &image, param)
 }
 
 // ---------------------- //
@@ -332,16 +1203,75 @@ impl ggez::event::EventHandler for State {
 // ---------------------- //
 
 pub fn run(conf: Conf) -> Result<(), Box<dyn Error>> {
+    match &conf.command {
+        Some(Command::Disasm(disasm_conf)) => return disasm(disasm_conf),
+        Some(Command::Asm(asm_conf)) => return asm(asm_conf),
+        None => (),
+    }
+
     let mut debug_view = DebugView::new(&conf)?;
     logger::setup(&conf, &mut debug_view)?;
 
-    let emulator = init_emulator(&conf)?;
+    let (mut emulator, profile) = init_emulator(&conf)?;
+    for addr in &conf.breakpoints {
+        emulator.debugger_mut().set_breakpoint(*addr);
+    }
+    for watchpoint in &conf.watchpoints {
+        emulator.debugger_mut().watch(watchpoint.clone());
+    }
+    let bindings = Bindings::new(&profile)?;
+    if conf.print_keymap {
+        print!("{}", bindings.describe());
+        return Ok(());
+    }
+    let movie = init_movie(&conf, emulator.as_mut(), &profile)?;
+    let mut video = init_video(&conf, emulator.as_ref(), &profile)?;
+
+    if let Some(limit) = conf.headless_limit() {
+        info!("Starting headless emulation");
+        let mut playback = match movie {
+            Some(Movie::Play(playback)) => Some(playback),
+            _ => None,
+        };
+        let mut samples = Vec::new();
+        headless::run(
+            emulator.as_mut(),
+            limit,
+            playback.as_mut(),
+            Some(&mut samples),
+            |emulator, draw| {
+                if let Some(Err(e)) = video.as_mut().map(|video| video.update(emulator, draw)) {
+                    error!("Failed to record video, recording stopped: {}", e);
+                    video = None;
+                }
+            },
+        );
+
+        if let Some(path) = &conf.wav {
+            info!("Writing audio to `{}`", path.display());
+            audio::write_wav(path, &samples)?;
+        }
+        if let Some(path) = &conf.screenshot {
+            info!("Writing screenshot to `{}`", path.display());
+            let framebuffer = emulator.framebuffer();
+            let colours = render::colours(framebuffer, &palette(&profile));
+            let (scale, style) = (conf.screenshot_scale as usize, conf.pixel_style);
+            screenshot::write_png(path, &colours, framebuffer.width, scale, style)?;
+        }
+        if let Some(video) = video {
+            video.finish()?;
+        }
+        return Ok(());
+    }
+
     debug_view.draw(&emulator)?;
 
-    let mut state = State::new(&conf, emulator, debug_view);
+    let mut state = State::new(
+        &conf, emulator, &profile, bindings, movie, video, debug_view,
+    );
 
     let window_setup = conf::WindowSetup::default()
-        .title(format!("memu ({}) - {}", conf.emulator, conf.rom_path).as_str())
+        .title(format!("memu ({}) - {}", state.emulator_kind, conf.rom_path()).as_str())
         .vsync(true);
 
     let (ref mut ctx, ref mut event_loop) = ContextBuilder::new("memu", "Mathijs Saey")
@@ -353,5 +1283,8 @@ pub fn run(conf: Conf) -> Result<(), Box<dyn Error>> {
     info!("Starting emulation loop in {} mode", state.progress_mode);
     event::run(ctx, event_loop, &mut state)?;
     info!("Emulation loop finished, shutting down");
+    if let Some(video) = state.video.take() {
+        video.finish()?;
+    }
     Ok(())
 }
@@ -0,0 +1,520 @@
+use log::*;
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use super::input::Input;
+use super::Emulator;
+
+// Every movie file starts with this line, followed by the rom hash, the seed and the settings
+const HEADER: &str = "memu movie 2";
+
+// ------ //
+// Errors //
+// ------ //
+
+#[derive(Debug)]
+pub enum MovieError {
+    /// The movie file could not be read or written
+    Io(io::Error),
+    /// A line of the movie file could not be parsed
+    Parse(usize, String),
+    /// The movie file does not contain a required field
+    Missing(&'static str),
+    /// The movie was recorded with other settings than it is played with
+    Mismatch {
+        setting: &'static str,
+        recorded: String,
+        played: String,
+    },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io(e) => write!(f, "Cannot access movie: {}", e),
+            MovieError::Parse(line, text) => write!(f, "Invalid movie, line {}: `{}`", line, text),
+            MovieError::Missing(field) => write!(f, "Invalid movie, missing `{}`", field),
+            MovieError::Mismatch {
+                setting,
+                recorded,
+                played,
+            } => write!(
+                f,
+                "Movie was recorded with {} `{}`, but is played with `{}`",
+                setting, recorded, played
+            ),
+        }
+    }
+}
+
+impl Error for MovieError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MovieError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MovieError {
+    fn from(e: io::Error) -> MovieError {
+        MovieError::Io(e)
+    }
+}
+
+// ------ //
+// Events //
+// ------ //
+
+/// A key press or release, along with the amount of cycles executed before it happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub cycle: u64,
+    pub input: Input,
+    pub pressed: bool,
+}
+
+impl Event {
+    fn parse(words: &[&str]) -> Option<Event> {
+        let (cycle, action, key) = match words {
+            [cycle, action, key] => (cycle.parse().ok()?, *action, *key),
+            _ => return None,
+        };
+        let pressed = match action {
+            "down" => true,
+            "up" => false,
+            _ => return None,
+        };
+        let key = u8::from_str_radix(key, 16).ok().filter(|key| *key <= 0xF)?;
+
+        Some(Event {
+            cycle,
+            input: Input::Keypad(key),
+            pressed,
+        })
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = if self.pressed { "down" } else { "up" };
+        match self.input {
+            Input::Keypad(key) => write!(f, "{} {} {:X}", self.cycle, action, key),
+        }
+    }
+}
+
+// -------- //
+// Settings //
+// -------- //
+
+/// Emulator settings which change the outcome of the inputs of a movie.
+///
+/// They are stored in the movie, which can only be played with the same settings.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Settings {
+    /// Name of the emulated platform
+    pub platform: String,
+    /// Name of the quirks preset, `default` for the quirks of the platform
+    pub quirks: String,
+    /// Time a single cycle takes, which sets the amount of cycles per timer tick
+    pub cycle_dt: Duration,
+    /// Whether the timers tick after a fixed amount of cycles
+    pub frame_locked: bool,
+    /// Name of the random number generator, `default` for the seeded one
+    pub random: String,
+}
+
+impl Settings {
+    fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "platform {}", self.platform)?;
+        writeln!(w, "quirks {}", self.quirks)?;
+        writeln!(w, "cycle-time {}", self.cycle_dt.as_nanos())?;
+        writeln!(w, "frame-locked {}", self.frame_locked)?;
+        writeln!(w, "random {}", self.random)
+    }
+
+    fn mismatch(&self, played: &Settings) -> Option<MovieError> {
+        let mismatch = |setting, recorded: String, played: String| {
+            Some(MovieError::Mismatch {
+                setting,
+                recorded,
+                played,
+            })
+        };
+        let nanos = |dt: Duration| format!("{}ns", dt.as_nanos());
+
+        if self.platform != played.platform {
+            mismatch("platform", self.platform.clone(), played.platform.clone())
+        } else if self.quirks != played.quirks {
+            mismatch("quirks", self.quirks.clone(), played.quirks.clone())
+        } else if self.cycle_dt != played.cycle_dt {
+            mismatch("cycle time", nanos(self.cycle_dt), nanos(played.cycle_dt))
+        } else if self.frame_locked != played.frame_locked {
+            let (recorded, played) = (self.frame_locked, played.frame_locked);
+            mismatch("frame locking", recorded.to_string(), played.to_string())
+        } else if self.random != played.random {
+            mismatch("generator", self.random.clone(), played.random.clone())
+        } else {
+            None
+        }
+    }
+}
+
+// ----- //
+// Movie //
+// ----- //
+
+/// Movie which is recorded or played while the emulator runs.
+pub enum Movie {
+    Record(Recorder),
+    Play(Playback),
+}
+
+/// Writes the inputs of a run to a movie file as they happen.
+///
+/// Every line is flushed immediately, so the movie survives a crash of the emulator.
+pub struct Recorder {
+    file: LineWriter<File>,
+}
+
+impl Recorder {
+    /// Start a movie of the rom with hash `rom`, emulated with a generator seeded with `seed`.
+    pub fn create(
+        path: &Path,
+        rom: &str,
+        seed: u64,
+        settings: &Settings,
+    ) -> Result<Recorder, MovieError> {
+        let mut file = LineWriter::new(File::create(path)?);
+        writeln!(file, "{}", HEADER)?;
+        writeln!(file, "rom {}", rom)?;
+        writeln!(file, "seed {}", seed)?;
+        settings.write(&mut file)?;
+        Ok(Recorder { file })
+    }
+
+    /// Record an input which happened after `cycle` cycles were executed.
+    pub fn record(&mut self, cycle: u64, input: Input, pressed: bool) {
+        let event = Event {
+            cycle,
+            input,
+            pressed,
+        };
+        if let Err(e) = writeln!(self.file, "{}", event) {
+            error!("Failed to record input: {}", e);
+        }
+    }
+}
+
+/// Feeds the inputs of a movie file to an emulator, on the cycles they were recorded on.
+pub struct Playback {
+    rom: String,
+    seed: u64,
+    settings: Settings,
+    events: VecDeque<Event>,
+    cycle_timer: Duration,
+}
+
+impl Playback {
+    pub fn read(path: &Path) -> Result<Playback, MovieError> {
+        Playback::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(content: &str) -> Result<Playback, MovieError> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next() {
+            Some((_, HEADER)) => (),
+            Some((idx, line)) => return Err(MovieError::Parse(idx, line.to_string())),
+            None => return Err(MovieError::Missing(HEADER)),
+        }
+
+        let mut rom = None;
+        let mut seed = None;
+        let mut platform = None;
+        let mut quirks = None;
+        let mut cycle_dt = None;
+        let mut frame_locked = None;
+        let mut random = None;
+        let mut events = VecDeque::new();
+
+        for (idx, line) in lines {
+            let err = || MovieError::Parse(idx, line.to_string());
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            match words.as_slice() {
+                ["rom", hash] => rom = Some(hash.to_string()),
+                ["seed", val] => seed = Some(val.parse().map_err(|_| err())?),
+                ["platform", name] => platform = Some(name.to_string()),
+                ["quirks", name] => quirks = Some(name.to_string()),
+                ["cycle-time", nanos] => {
+                    let nanos = nanos.parse().map_err(|_| err())?;
+                    cycle_dt = Some(Duration::from_nanos(nanos));
+                }
+                ["frame-locked", val] => frame_locked = Some(val.parse().map_err(|_| err())?),
+                ["random", name] => random = Some(name.to_string()),
+                words => {
+                    let event = Event::parse(words).ok_or_else(err)?;
+                    // Events are played in order, so they have to be recorded in order
+                    if events
+                        .back()
+                        .is_some_and(|prev: &Event| prev.cycle > event.cycle)
+                    {
+                        return Err(err());
+                    }
+                    events.push_back(event);
+                }
+            }
+        }
+
+        Ok(Playback {
+            rom: rom.ok_or(MovieError::Missing("rom"))?,
+            seed: seed.ok_or(MovieError::Missing("seed"))?,
+            settings: Settings {
+                platform: platform.ok_or(MovieError::Missing("platform"))?,
+                quirks: quirks.ok_or(MovieError::Missing("quirks"))?,
+                cycle_dt: cycle_dt.ok_or(MovieError::Missing("cycle-time"))?,
+                frame_locked: frame_locked.ok_or(MovieError::Missing("frame-locked"))?,
+                random: random.ok_or(MovieError::Missing("random"))?,
+            },
+            events,
+            cycle_timer: Duration::from_millis(0),
+        })
+    }
+
+    /// Hash of the rom the movie was recorded with.
+    pub fn rom(&self) -> &str {
+        &self.rom
+    }
+
+    /// Seed of the random number generator the movie was recorded with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Settings the movie was recorded with.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Fail if the movie is played with other settings than it was recorded with, as its inputs
+    /// would not lead to the same results.
+    pub fn check(&self, settings: &Settings) -> Result<(), MovieError> {
+        match self.settings.mismatch(settings) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether every input of the movie was played.
+    pub fn finished(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Advance the emulator by the amount of cycles that should have occured in the elapsed time.
+    pub fn advance(&mut self, emulator: &mut dyn Emulator, elapsed: Duration) -> bool {
+        let dt = emulator.cycle_dt();
+        self.cycle_timer += elapsed;
+
+        let mut cycles = 0;
+        while self.cycle_timer >= dt {
+            self.cycle_timer -= dt;
+            cycles += 1;
+        }
+
+        self.run(emulator, cycles)
+    }
+
+    /// Execute `cycles` cycles, the inputs of the movie are passed to the emulator in between.
+    pub fn run(&mut self, emulator: &mut dyn Emulator, cycles: u64) -> bool {
+        let end = emulator.cycles() + cycles;
+        let mut draw = false;
+
+        loop {
+            self.play(emulator);
+
+            let now = emulator.cycles();
+            if now >= end || emulator.debugger().hit().is_some() {
+                return draw;
+            }
+
+            let next = self
+                .events
+                .front()
+                .map_or(end, |event| event.cycle.min(end));
+            draw |= emulator.run(next - now);
+        }
+    }
+
+    // Pass the inputs which are due to the emulator
+    fn play(&mut self, emulator: &mut dyn Emulator) {
+        while let Some(event) = self.events.front() {
+            if event.cycle > emulator.cycles() {
+                return;
+            }
+
+            if event.pressed {
+                emulator.key_down(event.input);
+            } else {
+                emulator.key_up(event.input);
+            }
+
+            self.events.pop_front();
+            if self.events.is_empty() {
+                info!("Movie finished after {} cycles", emulator.cycles());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Event, MovieError, Playback, Settings, HEADER};
+    use crate::input::Input;
+
+    fn settings() -> Settings {
+        Settings {
+            platform: String::from("chip8"),
+            quirks: String::from("default"),
+            cycle_dt: Duration::from_millis(2),
+            frame_locked: false,
+            random: String::from("default"),
+        }
+    }
+
+    // A movie with the default settings, followed by `events`
+    fn movie(events: &str) -> String {
+        let mut header = format!("{}\nrom abc\nseed 42\n", HEADER).into_bytes();
+        settings().write(&mut header).unwrap();
+        String::from_utf8(header).unwrap() + events
+    }
+
+    fn parse_error(content: &str) -> MovieError {
+        match Playback::parse(content) {
+            Ok(_) => panic!("movie was accepted:\n{}", content),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn events_round_trip() {
+        for (cycle, key, pressed) in [(0, 0x0, true), (12, 0xA, false), (u64::MAX, 0xF, true)] {
+            let event = Event {
+                cycle,
+                input: Input::Keypad(key),
+                pressed,
+            };
+            let text = event.to_string();
+            let words: Vec<&str> = text.split_whitespace().collect();
+            assert_eq!(Event::parse(&words), Some(event));
+        }
+    }
+
+    #[test]
+    fn invalid_events_are_rejected() {
+        for text in ["12 down", "12 press A", "12 down 10", "-1 down A", "x up A"] {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            assert_eq!(Event::parse(&words), None, "`{}` was accepted", text);
+        }
+    }
+
+    #[test]
+    fn movies_are_read() {
+        let playback = Playback::parse(&movie("# comment\n\n10 down 5\n20 up 5\n")).unwrap();
+        assert_eq!(playback.rom(), "abc");
+        assert_eq!(playback.seed(), 42);
+        assert_eq!(playback.settings(), &settings());
+        assert_eq!(playback.events.len(), 2);
+        assert_eq!(playback.events[1].cycle, 20);
+    }
+
+    #[test]
+    fn events_on_the_same_cycle_are_accepted() {
+        assert!(Playback::parse(&movie("10 down 5\n10 down 6\n")).is_ok());
+    }
+
+    #[test]
+    fn out_of_order_events_are_rejected() {
+        let content = movie("20 down 5\n10 up 5\n");
+        let line = content.lines().count();
+        assert!(matches!(parse_error(&content), MovieError::Parse(l, _) if l == line));
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        for content in [
+            movie("10 down G\n"),
+            movie("seed x\n"),
+            movie("cycle-time 2ms\n"),
+        ] {
+            assert!(matches!(parse_error(&content), MovieError::Parse(..)));
+        }
+        // Older movies do not contain the settings
+        assert!(matches!(
+            parse_error("memu movie 1\n"),
+            MovieError::Parse(1, _)
+        ));
+        assert!(matches!(parse_error(""), MovieError::Missing(_)));
+    }
+
+    #[test]
+    fn missing_fields_are_rejected() {
+        let full = movie("");
+        let fields = [
+            "rom",
+            "seed",
+            "platform",
+            "quirks",
+            "cycle-time",
+            "frame-locked",
+            "random",
+        ];
+        for field in fields.iter() {
+            let content: String = full
+                .lines()
+                .filter(|line| !line.starts_with(&format!("{} ", field)))
+                .map(|line| format!("{}\n", line))
+                .collect();
+            assert!(
+                matches!(parse_error(&content), MovieError::Missing(f) if f == *field),
+                "missing `{}` was accepted",
+                field
+            );
+        }
+    }
+
+    #[test]
+    fn movies_are_only_played_with_the_same_settings() {
+        let playback = Playback::parse(&movie("")).unwrap();
+        assert!(playback.check(&settings()).is_ok());
+
+        let changes: [fn(&mut Settings); 5] = [
+            |s| s.platform = String::from("schip"),
+            |s| s.quirks = String::from("vip"),
+            |s| s.cycle_dt = Duration::from_millis(1),
+            |s| s.frame_locked = true,
+            |s| s.random = String::from("vip"),
+        ];
+        for change in changes.iter() {
+            let mut played = settings();
+            change(&mut played);
+            assert!(matches!(
+                playback.check(&played),
+                Err(MovieError::Mismatch { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use clap::ValueEnum;
+use directories::ProjectDirs;
+use log::*;
+use serde::Deserialize;
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::keys::Binding;
+use super::{EmulatorKind, PalettePreset, QuirksPreset, RandomMode};
+
+// Profiles shipped with memu, users can add their own in `user_database`
+const BUNDLED: &str = include_str!("../roms.toml");
+const DATABASE_NAME: &str = "roms.toml";
+
+// ------ //
+// Errors //
+// ------ //
+
+#[derive(Debug)]
+pub enum ProfileError {
+    /// A rom database could not be parsed
+    Database(String, toml::de::Error),
+    /// A colour is not written as `#RRGGBB`
+    Colour(String),
+    /// A key binding does not have the `<key>=<binding>` form, or refers to an unknown key
+    Key(String),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProfileError::Database(path, e) => write!(f, "Invalid rom database `{}`: {}", path, e),
+            ProfileError::Colour(c) => write!(f, "Invalid colour `{}`, expected `#RRGGBB`", c),
+            ProfileError::Key(k) => write!(f, "Invalid key binding `{}`", k),
+        }
+    }
+}
+
+impl Error for ProfileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProfileError::Database(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// ------- //
+// Profile //
+// ------- //
+
+/// Settings used to run a specific rom.
+///
+/// Every field is optional: unset fields are taken from a less specific source, falling back to
+/// the defaults of the emulator.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    /// Human readable name of the rom
+    pub name: Option<String>,
+    pub platform: Option<EmulatorKind>,
+    pub quirks: Option<QuirksPreset>,
+    pub cycles_per_frame: Option<u32>,
+    /// Instructions executed per second, used instead of `cycles_per_frame` when both are set
+    pub ips: Option<u32>,
+    /// Tick the timers after a whole amount of instructions, instead of after 16.6 ms
+    pub frame_locked: Option<bool>,
+    /// Random number generator of the chip8 `Cxkk` instruction
+    pub random: Option<RandomMode>,
+    /// Keyboard key names mapped onto keypad keys or controls, added to the default layout
+    pub keys: BTreeMap<String, Binding>,
+    /// Gamepad button names mapped onto keypad keys or controls, added to the default layout
+    pub buttons: BTreeMap<String, Binding>,
+    /// Named palette, which provides the colours not set in `colours`
+    pub palette: Option<PalettePreset>,
+    /// Background, foreground and bitplane colours
+    pub colours: Option<Vec<Colour>>,
+}
+
+impl Profile {
+    /// Combine two profiles, fields set in `other` take precedence.
+    ///
+    /// A palette set in `other` replaces the colours of `self` as well, and either speed set in
+    /// `other` replaces both speeds of `self`.
+    pub fn merge(mut self, other: Profile) -> Profile {
+        self.keys.extend(other.keys);
+        self.buttons.extend(other.buttons);
+        if other.palette.is_some() {
+            self.colours = None;
+        }
+        if other.cycles_per_frame.is_some() || other.ips.is_some() {
+            self.cycles_per_frame = None;
+            self.ips = None;
+        }
+        Profile {
+            name: other.name.or(self.name),
+            platform: other.platform.or(self.platform),
+            quirks: other.quirks.or(self.quirks),
+            cycles_per_frame: other.cycles_per_frame.or(self.cycles_per_frame),
+            ips: other.ips.or(self.ips),
+            frame_locked: other.frame_locked.or(self.frame_locked),
+            random: other.random.or(self.random),
+            keys: self.keys,
+            buttons: self.buttons,
+            palette: other.palette.or(self.palette),
+            colours: other.colours.or(self.colours),
+        }
+    }
+}
+
+/// An RGB colour, written as `#RRGGBB`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct Colour(pub u8, pub u8, pub u8);
+
+impl FromStr for Colour {
+    type Err = ProfileError;
+
+    fn from_str(s: &str) -> Result<Colour, ProfileError> {
+        let err = || ProfileError::Colour(s.to_string());
+        let hex = s.strip_prefix('#').ok_or_else(err)?;
+
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(err());
+        }
+
+        let component = |idx| u8::from_str_radix(&hex[idx..idx + 2], 16).map_err(|_| err());
+        Ok(Colour(component(0)?, component(2)?, component(4)?))
+    }
+}
+
+impl TryFrom<String> for Colour {
+    type Error = ProfileError;
+
+    fn try_from(s: String) -> Result<Colour, ProfileError> {
+        s.parse()
+    }
+}
+
+// -------- //
+// Database //
+// -------- //
+
+/// Profiles, keyed by the SHA-1 hash of the rom they belong to, and the profiles of the
+/// emulators, which are used for every rom on that emulator.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Database {
+    #[serde(default)]
+    roms: HashMap<String, Profile>,
+    #[serde(default)]
+    emulators: HashMap<String, Profile>,
+}
+
+impl Database {
+    fn parse(name: &str, content: &str) -> Result<Database, ProfileError> {
+        toml::from_str(content).map_err(|e| ProfileError::Database(name.to_string(), e))
+    }
+
+    fn read(path: &Path) -> Result<Database, Box<dyn Error>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read rom database `{}`: {}", path.display(), e))?;
+        Ok(Database::parse(&path.display().to_string(), &content)?)
+    }
+
+    fn get(&mut self, hash: &str) -> Profile {
+        self.roms.remove(hash).unwrap_or_default()
+    }
+
+    fn emulator(&mut self, kind: EmulatorKind) -> Profile {
+        // Emulators are stored under the name used on the command line
+        kind.to_possible_value()
+            .and_then(|name| self.emulators.remove(name.get_name()))
+            .unwrap_or_default()
+    }
+}
+
+/// Location of the rom database of the user, unless another one is passed on the command line.
+pub fn user_database() -> Option<PathBuf> {
+    ProjectDirs::from("", "", "memu").map(|dirs| dirs.config_dir().join(DATABASE_NAME))
+}
+
+/// Hash of a rom, as used to look it up in the database.
+pub fn hash(rom: &[u8]) -> String {
+    sha1::Sha1::from(rom).digest().to_string()
+}
+
+/// Find the profile of a rom in the bundled database and the database of the user.
+///
+/// Entries in the database of the user take precedence over the bundled ones. The default user
+/// database is optional, a database passed explicitly has to exist. The profile of the rom,
+/// with `overrides` applied, takes precedence over the profile of its emulator; which falls back
+/// to chip8. Unknown roms result in the profile of their emulator.
+pub fn lookup(
+    rom: &[u8],
+    user_db: Option<&Path>,
+    overrides: Profile,
+) -> Result<Profile, Box<dyn Error>> {
+    let hash = hash(rom);
+    info!("Looking up profile of rom `{}`", hash);
+
+    let bundled = Database::parse("bundled", BUNDLED)?;
+    let user = match (user_db, user_database()) {
+        (Some(path), _) => Database::read(path)?,
+        (None, Some(path)) if path.exists() => Database::read(&path)?,
+        _ => Database::default(),
+    };
+
+    Ok(resolve(&hash, bundled, user, overrides))
+}
+
+/// Combine the profiles of a rom and of its emulator found in both databases, see `lookup`.
+fn resolve(hash: &str, mut bundled: Database, mut user: Database, overrides: Profile) -> Profile {
+    let mut profile = bundled.get(hash).merge(user.get(hash)).merge(overrides);
+    if let Some(name) = &profile.name {
+        info!("Using profile of `{}`", name);
+    }
+
+    let kind = *profile.platform.get_or_insert(EmulatorKind::Chip8);
+    let emulator = bundled.emulator(kind).merge(user.emulator(kind));
+    emulator.merge(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::{lookup, resolve, Colour, Database, Profile, BUNDLED};
+    use crate::input::Input;
+    use crate::keys::Binding;
+    use crate::{EmulatorKind, PalettePreset, QuirksPreset};
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn database(content: &str) -> Database {
+        Database::parse("test", content).unwrap()
+    }
+
+    // Profiles
+    // --------
+
+    #[test]
+    fn user_profiles_take_precedence_over_bundled_ones() {
+        let bundled = database(&format!(
+            "[roms.{}]\nname = \"Bundled\"\nquirks = \"schip\"\nips = 1000\n\
+             [roms.{0}.keys]\nq = 0x4\nw = 0x5",
+            HASH
+        ));
+        let user = database(&format!(
+            "[roms.{}]\nname = \"User\"\ncycles-per-frame = 20\n[roms.{0}.keys]\nw = 0x6",
+            HASH
+        ));
+
+        let profile = resolve(HASH, bundled, user, Profile::default());
+        assert_eq!(profile.name.as_deref(), Some("User"));
+        assert!(matches!(profile.quirks, Some(QuirksPreset::Schip)));
+        // Either speed replaces both speeds of the bundled profile
+        assert_eq!(profile.cycles_per_frame, Some(20));
+        assert_eq!(profile.ips, None);
+        assert_eq!(profile.keys.len(), 2);
+        assert_eq!(profile.keys["q"], Binding::Input(Input::Keypad(0x4)));
+        assert_eq!(profile.keys["w"], Binding::Input(Input::Keypad(0x6)));
+    }
+
+    #[test]
+    fn overrides_take_precedence_over_the_databases() {
+        let bundled = database(&format!("[roms.{}]\nplatform = \"schip\"", HASH));
+        let user = database(&format!("[roms.{}]\npalette = \"green\"", HASH));
+        let overrides = Profile {
+            platform: Some(EmulatorKind::XoChip),
+            palette: Some(PalettePreset::Amber),
+            ..Profile::default()
+        };
+
+        let profile = resolve(HASH, bundled, user, overrides);
+        assert!(matches!(profile.platform, Some(EmulatorKind::XoChip)));
+        assert!(matches!(profile.palette, Some(PalettePreset::Amber)));
+    }
+
+    #[test]
+    fn rom_profiles_take_precedence_over_emulator_profiles() {
+        let bundled = database(&format!(
+            "[roms.{}]\nplatform = \"schip\"\nframe-locked = false\n\
+             [emulators.schip]\nframe-locked = true\ncycles-per-frame = 30\n\
+             [emulators.chip8]\nquirks = \"vip\"",
+            HASH
+        ));
+        let user = database("[emulators.schip]\npalette = \"lcd\"\ncolours = [\"#102030\"]");
+
+        let profile = resolve(HASH, bundled, user, Profile::default());
+        assert!(matches!(profile.platform, Some(EmulatorKind::SuperChip)));
+        assert_eq!(profile.frame_locked, Some(false));
+        assert_eq!(profile.cycles_per_frame, Some(30));
+        // Only the profile of the emulator of the rom applies
+        assert!(profile.quirks.is_none());
+        assert!(matches!(profile.palette, Some(PalettePreset::Lcd)));
+        assert_eq!(profile.colours, Some(vec![Colour(0x10, 0x20, 0x30)]));
+    }
+
+    #[test]
+    fn a_palette_replaces_the_colours_of_less_specific_profiles() {
+        let bundled = database(&format!("[roms.{}]\ncolours = [\"#102030\"]", HASH));
+        let user = database(&format!("[roms.{}]\npalette = \"octo\"", HASH));
+
+        let profile = resolve(HASH, bundled, user, Profile::default());
+        assert!(matches!(profile.palette, Some(PalettePreset::Octo)));
+        assert_eq!(profile.colours, None);
+    }
+
+    #[test]
+    fn unknown_roms_fall_back_to_the_defaults() {
+        let bundled = database(&format!("[roms.{}]\nname = \"Known\"", HASH));
+
+        let profile = resolve(
+            "ffffffffffffffffffffffffffffffffffffffff",
+            bundled,
+            Database::default(),
+            Profile::default(),
+        );
+        assert!(matches!(profile.platform, Some(EmulatorKind::Chip8)));
+        assert_eq!(profile.name, None);
+        assert!(profile.quirks.is_none());
+        assert_eq!(profile.cycles_per_frame, None);
+        assert_eq!(profile.ips, None);
+        assert_eq!(profile.frame_locked, None);
+        assert!(profile.random.is_none());
+        assert!(profile.keys.is_empty() && profile.buttons.is_empty());
+        assert!(profile.palette.is_none());
+        assert_eq!(profile.colours, None);
+    }
+
+    #[test]
+    fn unknown_roms_are_looked_up_in_the_bundled_database() {
+        let user_db = env::temp_dir().join("memu-unknown-rom.toml");
+        fs::write(&user_db, "").unwrap();
+
+        let profile = lookup(&[0xFF; 16], Some(&user_db), Profile::default()).unwrap();
+        assert_eq!(profile.name, None);
+        assert!(matches!(profile.platform, Some(EmulatorKind::Chip8)));
+    }
+
+    #[test]
+    fn a_missing_user_database_passed_explicitly_is_an_error() {
+        let user_db = env::temp_dir().join("memu-missing").join("roms.toml");
+        assert!(lookup(&[0xFF; 16], Some(&user_db), Profile::default()).is_err());
+    }
+
+    // Bundled database
+    // ----------------
+
+    #[test]
+    fn bundled_database_is_valid() {
+        let bundled = database(BUNDLED);
+        for (key, profile) in bundled.roms.iter() {
+            assert!(
+                key.len() == 40
+                    && key
+                        .bytes()
+                        .all(|c| c.is_ascii_digit() || (b'a'..=b'f').contains(&c)),
+                "`{}` is not a SHA-1 hash",
+                key
+            );
+            assert!(profile.name.is_some(), "`{}` has no name", key);
+        }
+    }
+
+    #[cfg(feature = "chip8")]
+    #[test]
+    fn bundled_database_knows_the_flags_test() {
+        let source = include_str!("../tests/roms/flags.asm");
+        let rom = crate::chip8::asm::assemble(source).unwrap();
+
+        let profile = resolve(
+            &super::hash(&rom),
+            database(BUNDLED),
+            Database::default(),
+            Profile::default(),
+        );
+        assert_eq!(profile.name.as_deref(), Some("Flags test"));
+        assert!(matches!(profile.quirks, Some(QuirksPreset::Vip)));
+    }
+
+    // Colours
+    // -------
+
+    #[test]
+    fn colours_are_parsed_from_hex_triplets() {
+        assert_eq!("#000000".parse::<Colour>().unwrap(), Colour(0, 0, 0));
+        assert_eq!(
+            "#FF8000".parse::<Colour>().unwrap(),
+            Colour(0xFF, 0x80, 0x00)
+        );
+        assert_eq!(
+            "#a0b1c2".parse::<Colour>().unwrap(),
+            Colour(0xA0, 0xB1, 0xC2)
+        );
+    }
+
+    #[test]
+    fn malformed_colours_are_rejected() {
+        for colour in [
+            "FF8000", "#FF800", "#FF80001", "#GG8000", "#FF 800", "#FF80é", "",
+        ]
+        .iter()
+        {
+            assert!(
+                colour.parse::<Colour>().is_err(),
+                "`{}` was accepted",
+                colour
+            );
+        }
+    }
+
+    #[test]
+    fn colours_are_deserialized_from_strings() {
+        let user = database(&format!(
+            "[roms.{}]\ncolours = [\"#123456\", \"#abcdef\"]",
+            HASH
+        ));
+        assert_eq!(
+            user.roms[HASH].colours,
+            Some(vec![Colour(0x12, 0x34, 0x56), Colour(0xAB, 0xCD, 0xEF)])
+        );
+        assert!(Database::parse("test", &format!("[roms.{}]\ncolours = [\"red\"]", HASH)).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use clap::ValueEnum;
+
+use super::framebuffer::Framebuffer;
+use super::profile::Colour;
+use super::{Emulator, FRAME_TIME};
+
+/// Look of the pixels of the emulator, when they are drawn larger than a single pixel.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelStyle {
+    /// Solid squares
+    Plain,
+    /// Squares separated by dark lines
+    Grid,
+    /// Rows separated by dark lines, like a CRT
+    Scanlines,
+}
+
+/// Colour of every pixel of a framebuffer.
+pub fn colours(framebuffer: Framebuffer, palette: &[Colour]) -> Vec<Colour> {
+    framebuffer
+        .pixels
+        .iter()
+        .map(|px| palette[*px as usize % palette.len()])
+        .collect()
+}
+
+/// Enlarge an image `width` pixels wide, so every pixel becomes a `scale` by `scale` square.
+///
+/// The lines drawn by `style` take up the bottom row and, for a grid, the rightmost column of
+/// every square; they are only drawn when the squares are at least 2 pixels wide.
+pub fn render(colours: &[Colour], width: usize, scale: usize, style: PixelStyle) -> Vec<Colour> {
+    let mut image = Vec::with_capacity(colours.len() * scale * scale);
+
+    for row in colours.chunks(width) {
+        for y in 0..scale {
+            for colour in row {
+                for x in 0..scale {
+                    let line = match style {
+                        PixelStyle::Plain => false,
+                        PixelStyle::Grid => x == scale - 1 || y == scale - 1,
+                        PixelStyle::Scanlines => y == scale - 1,
+                    };
+
+                    if line && scale >= 2 {
+                        image.push(darken(*colour));
+                    } else {
+                        image.push(*colour);
+                    }
+                }
+            }
+        }
+    }
+
+    image
+}
+
+fn darken(Colour(r, g, b): Colour) -> Colour {
+    Colour(r / 2, g / 2, b / 2)
+}
+
+// ----------- //
+// Persistence //
+// ----------- //
+
+/// Simulates the afterglow of a phosphor display, which reduces the flicker of sprites that are
+/// erased and redrawn.
+///
+/// Pixels which are set are shown at once, pixels which are cleared fade out to the background
+/// colour. Only the shown colours are affected, the emulator keeps its own framebuffer.
+pub struct Persistence {
+    // Fraction of its brightness a cleared pixel keeps every frame
+    decay: f32,
+    // Amount of cycles executed when the pixels were last updated
+    cycles: u64,
+    shown: Vec<[f32; 3]>,
+}
+
+impl Persistence {
+    pub fn new(decay: f32) -> Persistence {
+        Persistence {
+            decay,
+            cycles: 0,
+            shown: Vec::new(),
+        }
+    }
+
+    /// Blend the framebuffer of the emulator into the shown pixels.
+    ///
+    /// Pixels fade by the amount of emulated time since the previous update, so the afterglow
+    /// lasts as long at every speed. Returns whether the shown colours changed.
+    pub fn update(&mut self, emulator: &dyn Emulator, palette: &[Colour]) -> bool {
+        // Rewinding or loading a state goes back in time, which does not fade pixels
+        let cycles = emulator.cycles();
+        let elapsed = emulator
+            .cycle_dt()
+            .mul_f64(cycles.saturating_sub(self.cycles) as f64);
+        self.cycles = cycles;
+
+        let framebuffer = emulator.framebuffer();
+        let background = rgb(palette[0]);
+        let factor = self
+            .decay
+            .powf(elapsed.as_secs_f32() / FRAME_TIME.as_secs_f32());
+
+        if self.shown.len() != framebuffer.pixels.len() {
+            self.shown = vec![background; framebuffer.pixels.len()];
+        }
+
+        let mut changed = false;
+        for (shown, px) in self.shown.iter_mut().zip(framebuffer.pixels) {
+            let old = *shown;
+
+            *shown = if *px != 0 {
+                rgb(palette[*px as usize % palette.len()])
+            } else {
+                let mut faded = [0.0; 3];
+                for (idx, channel) in faded.iter_mut().enumerate() {
+                    *channel = background[idx] + (shown[idx] - background[idx]) * factor;
+                    // Snap to the background once the difference is no longer visible
+                    if (*channel - background[idx]).abs() < 1.0 {
+                        *channel = background[idx];
+                    }
+                }
+                faded
+            };
+
+            changed |= old != *shown;
+        }
+
+        changed
+    }
+
+    /// Colour of every pixel, as they are shown.
+    pub fn colours(&self) -> Vec<Colour> {
+        self.shown
+            .iter()
+            .map(|[r, g, b]| Colour(*r as u8, *g as u8, *b as u8))
+            .collect()
+    }
+}
+
+fn rgb(Colour(r, g, b): Colour) -> [f32; 3] {
+    [r as f32, g as f32, b as f32]
+}
+
+/// Parse the decay of the persistence filter, as passed on the command line.
+pub fn parse_decay(s: &str) -> Result<f32, String> {
+    match s.parse() {
+        Ok(decay) if (0.0..1.0).contains(&decay) => Ok(decay),
+        _ => Err(format!("`{}` is not a number from 0 up to 1", s)),
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+
+/// Bounded history of emulator states.
+///
+/// Only the most recent state is stored in full. Older states are stored as a delta which turns
+/// a state into its predecessor. Most of the state of an emulator (i.e. its memory) does not
+/// change between frames, so these deltas are usually tiny.
+pub struct Rewind {
+    capacity: usize,
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            capacity,
+            current: None,
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Add a state to the history, dropping the oldest state if the buffer is full.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.capacity == 0 || self.current.as_ref() == Some(&state) {
+            return;
+        }
+
+        if let Some(prev) = self.current.take() {
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(Delta::new(&state, &prev));
+        }
+
+        self.current = Some(state);
+    }
+
+    /// Step back to the previous state, returns `None` if the history is exhausted.
+    pub fn pop(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        let current = self.current.as_mut()?;
+        delta.apply(current);
+        Some(current)
+    }
+}
+
+// ----- //
+// Delta //
+// ----- //
+
+/// Difference between two states.
+///
+/// The states are XOR'ed together, after which runs of equal bytes (zeroes in the XOR) are
+/// skipped. Each change is stored as a skip length, a run length and the XOR'ed bytes of the run.
+struct Delta {
+    len: usize,
+    runs: Vec<u8>,
+}
+
+impl Delta {
+    /// Create a delta which turns `from` into `to`.
+    fn new(from: &[u8], to: &[u8]) -> Delta {
+        let len = from.len().max(to.len());
+        let xor: Vec<u8> = (0..len)
+            .map(|idx| from.get(idx).unwrap_or(&0) ^ to.get(idx).unwrap_or(&0))
+            .collect();
+
+        let mut runs = Vec::new();
+        let mut idx = 0;
+
+        while idx < len {
+            let start = idx;
+            while idx < len && xor[idx] == 0 && idx - start < u16::MAX as usize {
+                idx += 1;
+            }
+            let skip = idx - start;
+
+            let start = idx;
+            while idx < len && xor[idx] != 0 && idx - start < u16::MAX as usize {
+                idx += 1;
+            }
+
+            runs.extend_from_slice(&(skip as u16).to_le_bytes());
+            runs.extend_from_slice(&((idx - start) as u16).to_le_bytes());
+            runs.extend_from_slice(&xor[start..idx]);
+        }
+
+        Delta { len: to.len(), runs }
+    }
+
+    fn apply(&self, state: &mut Vec<u8>) {
+        let len = state.len().max(self.len);
+        state.resize(len, 0);
+
+        let mut pos = 0;
+        let mut runs = self.runs.as_slice();
+
+        while !runs.is_empty() {
+            let skip = u16::from_le_bytes([runs[0], runs[1]]) as usize;
+            let run = u16::from_le_bytes([runs[2], runs[3]]) as usize;
+            pos += skip;
+
+            for (byte, xor) in state[pos..pos + run].iter_mut().zip(&runs[4..4 + run]) {
+                *byte ^= xor;
+            }
+
+            pos += run;
+            runs = &runs[4 + run..];
+        }
+
+        state.truncate(self.len);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Delta, Rewind};
+
+    // Check that the delta from `from` to `to` turns `from` into `to`
+    fn round_trip(from: &[u8], to: &[u8]) -> Delta {
+        let delta = Delta::new(from, to);
+        let mut state = from.to_vec();
+        delta.apply(&mut state);
+        assert_eq!(state, to);
+        delta
+    }
+
+    #[test]
+    fn identical_states_produce_a_tiny_delta() {
+        let state = vec![0xAB; 4096];
+        let delta = round_trip(&state, &state);
+        assert!(delta.runs.len() <= 4);
+    }
+
+    #[test]
+    fn states_which_differ_everywhere() {
+        let from: Vec<u8> = (0..4096).map(|idx| idx as u8).collect();
+        let to: Vec<u8> = from.iter().map(|byte| !byte).collect();
+        round_trip(&from, &to);
+    }
+
+    #[test]
+    fn changes_at_the_start_and_end() {
+        let from = vec![0; 100];
+        let mut to = from.clone();
+        to[0] = 1;
+        to[1] = 2;
+        to[98] = 3;
+        to[99] = 4;
+        let delta = round_trip(&from, &to);
+        // Two changed runs around a single skipped run
+        assert_eq!(delta.runs.len(), 2 * 4 + 4);
+    }
+
+    #[test]
+    fn states_of_different_lengths() {
+        // Switching to a larger screen grows the state, rewinding shrinks it again
+        let small = vec![7; 100];
+        let mut large = vec![7; 300];
+        large[150] = 1;
+        round_trip(&small, &large);
+        round_trip(&large, &small);
+        round_trip(&[], &large);
+        round_trip(&large, &[]);
+    }
+
+    #[test]
+    fn runs_longer_than_a_length_field() {
+        // XO-Chip states hold 64 KiB of memory
+        let from = vec![0; 200_000];
+        let mut to = from.clone();
+        for byte in &mut to[10..140_000] {
+            *byte = 0xFF;
+        }
+        to[199_999] = 1;
+        round_trip(&from, &to);
+    }
+
+    #[test]
+    fn oldest_states_are_dropped_at_capacity() {
+        let mut rewind = Rewind::new(3);
+        for state in 0..5u8 {
+            rewind.push(vec![state; 16]);
+        }
+
+        for state in (1..4u8).rev() {
+            assert_eq!(rewind.pop(), Some(&[state; 16][..]));
+        }
+        assert_eq!(rewind.pop(), None);
+        assert_eq!(rewind.pop(), None);
+
+        // The history continues from the oldest state which was kept
+        rewind.push(vec![9; 16]);
+        assert_eq!(rewind.pop(), Some(&[1; 16][..]));
+    }
+
+    #[test]
+    fn repeated_states_are_stored_once() {
+        let mut rewind = Rewind::new(10);
+        rewind.push(vec![1]);
+        rewind.push(vec![2]);
+        rewind.push(vec![2]);
+        assert_eq!(rewind.pop(), Some(&[1][..]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn zero_capacity_disables_rewinding() {
+        let mut rewind = Rewind::new(0);
+        rewind.push(vec![1]);
+        rewind.push(vec![2]);
+        assert_eq!(rewind.pop(), None);
+    }
+}
@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+// Every save state file starts with this magic number, followed by the version of the format
+const MAGIC: &[u8; 4] = b"MEMU";
+const VERSION: u16 = 1;
+
+// ------ //
+// Errors //
+// ------ //
+
+#[derive(Debug)]
+pub enum StateError {
+    /// The data does not start with the save state magic number
+    NotAState,
+    /// The data was written by an incompatible version of memu
+    Version(u16),
+    /// The state was created by another emulator
+    Emulator(String),
+    /// The data ended before the complete state was read
+    Truncated,
+    /// The data contains a value that does not make sense for the emulator
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::NotAState => write!(f, "Not a memu save state"),
+            StateError::Version(v) => write!(f, "Unsupported save state version: {}", v),
+            StateError::Emulator(e) => write!(f, "Save state was created by the {} emulator", e),
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::Invalid(what) => write!(f, "Save state contains an invalid {}", what),
+        }
+    }
+}
+
+impl Error for StateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+// ------- //
+// Writing //
+// ------- //
+
+/// Serializes values into a little endian byte buffer.
+#[derive(Default)]
+pub struct StateWriter(Vec<u8>);
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter(Vec::new())
+    }
+
+    pub fn u8(&mut self, val: u8) {
+        self.0.push(val);
+    }
+
+    pub fn u16(&mut self, val: u16) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, val: u32) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, val: u64) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u128(&mut self, val: u128) {
+        self.0.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.u8(val as u8);
+    }
+
+    pub fn duration(&mut self, val: Duration) {
+        self.u64(val.as_nanos() as u64);
+    }
+
+    /// Write a fixed amount of bytes, the reader needs to know the length.
+    pub fn bytes(&mut self, val: &[u8]) {
+        self.0.extend_from_slice(val);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+// ------- //
+// Reading //
+// ------- //
+
+/// Reads values written by a [`StateWriter`].
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, pos: 0 }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.pos + len > self.data.len() {
+            return Err(StateError::Truncated);
+        }
+        let res = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(res)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut res = [0; N];
+        res.copy_from_slice(self.bytes(N)?);
+        Ok(res)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn u128(&mut self) -> Result<u128, StateError> {
+        Ok(u128::from_le_bytes(self.array()?))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("boolean")),
+        }
+    }
+
+    pub fn duration(&mut self) -> Result<Duration, StateError> {
+        Ok(Duration::from_nanos(self.u64()?))
+    }
+}
+
+// ----- //
+// Files //
+// ----- //
+
+/// Write the state of `emulator` to a save state file.
+pub fn write_file<P: AsRef<Path>>(path: P, emulator: &str, state: &[u8]) -> std::io::Result<()> {
+    let mut w = StateWriter::new();
+    w.bytes(MAGIC);
+    w.u16(VERSION);
+    w.u8(emulator.len() as u8);
+    w.bytes(emulator.as_bytes());
+    w.bytes(state);
+    fs::write(path, w.into_bytes())
+}
+
+/// Read a save state file, and verify it was created for `emulator`.
+pub fn read_file<P: AsRef<Path>>(path: P, emulator: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let mut r = StateReader::new(&data);
+
+    if r.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err(Box::new(StateError::NotAState));
+    }
+
+    let version = r.u16()?;
+    if version != VERSION {
+        return Err(Box::new(StateError::Version(version)));
+    }
+
+    let len = r.u8()? as usize;
+    let name = String::from_utf8_lossy(r.bytes(len)?).into_owned();
+    if name != emulator {
+        return Err(Box::new(StateError::Emulator(name)));
+    }
+
+    Ok(data[r.pos..].to_vec())
+}
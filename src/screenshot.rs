@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use super::profile::Colour;
+use super::render::{self, PixelStyle};
+
+/// Write the colours of a screen `width` pixels wide to a PNG file.
+///
+/// Every pixel of the screen becomes a `scale` by `scale` square of the image, drawn in `style`.
+pub fn write_png(
+    path: &Path,
+    colours: &[Colour],
+    width: usize,
+    scale: usize,
+    style: PixelStyle,
+) -> Result<(), Box<dyn Error>> {
+    let height = colours.len() / width;
+
+    let data: Vec<u8> = render::render(colours, width, scale, style)
+        .iter()
+        .flat_map(|Colour(r, g, b)| vec![*r, *g, *b])
+        .collect();
+
+    let file = BufWriter::new(File::create(path)?);
+    let (width, height) = ((width * scale) as u32, (height * scale) as u32);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+/// First `<base>.screenshot<n>.png` path which does not exist yet.
+pub fn next_path(base: &str) -> PathBuf {
+    (1..)
+        .map(|n| PathBuf::from(format!("{}.screenshot{}.png", base, n)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
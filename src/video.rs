@@ -0,0 +1,254 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use gif::SetParameter;
+
+use super::profile::Colour;
+use super::Emulator;
+
+// Y4M streams have a fixed frame rate, the refresh rate of the display
+const Y4M_FPS: u128 = 60;
+// Most GIF viewers slow down frames which are shown for less than 2 centiseconds
+const GIF_MIN_DELAY: u64 = 2;
+
+/// Records the screen of an emulator to an animated GIF or a Y4M stream.
+///
+/// A frame is captured every time the emulator redraws its screen. Frames are timed by the
+/// emulated time, so recordings of stepped or sped-up emulation play back at the normal speed.
+pub struct Video {
+    encoder: Encoder,
+    palette: Vec<Colour>,
+    width: usize,
+    height: usize,
+    scale: usize,
+    // Amount of cycles executed and emulated time elapsed since the recording started
+    cycles: u64,
+    time: Duration,
+}
+
+enum Encoder {
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        // Last frame, written once it is known how long it is shown
+        pending: Vec<u8>,
+        // Centiseconds of frames written so far
+        written: u64,
+    },
+    Y4m {
+        file: BufWriter<File>,
+        // Last frame, written for every tick of the frame rate until the next redraw
+        frame: Vec<u8>,
+        // Amount of frames written so far
+        written: u64,
+    },
+}
+
+impl Video {
+    /// Start recording, the format is picked from the extension of `path`: `.gif` or `.y4m`.
+    ///
+    /// Every pixel of the emulator becomes a `scale` by `scale` square of the video.
+    pub fn create(
+        path: &Path,
+        emulator: &dyn Emulator,
+        palette: &[Colour],
+        scale: usize,
+    ) -> Result<Video, Box<dyn Error>> {
+        let framebuffer = emulator.framebuffer();
+        let (width, height) = (framebuffer.width * scale, framebuffer.height * scale);
+        let pixels = indices(emulator, palette, scale);
+
+        let create = || File::create(path).map(BufWriter::new);
+
+        let encoder = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gif") => {
+                let mut colours: Vec<u8> = palette
+                    .iter()
+                    .flat_map(|Colour(r, g, b)| vec![*r, *g, *b])
+                    .collect();
+                // The palette of a GIF contains a power of two amount of colours
+                colours.resize(palette.len().next_power_of_two().max(2) * 3, 0);
+
+                let mut encoder =
+                    gif::Encoder::new(create()?, width as u16, height as u16, &colours)?;
+                encoder.set(gif::Repeat::Infinite)?;
+                Encoder::Gif {
+                    encoder,
+                    pending: pixels,
+                    written: 0,
+                }
+            }
+            Some("y4m") => {
+                let mut file = create()?;
+                writeln!(
+                    file,
+                    "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                    width, height, Y4M_FPS
+                )?;
+                Encoder::Y4m {
+                    file,
+                    frame: yuv(&pixels, palette),
+                    written: 0,
+                }
+            }
+            _ => {
+                let msg = format!(
+                    "Unsupported video format `{}`, use `.gif` or `.y4m`",
+                    path.display()
+                );
+                return Err(msg.into());
+            }
+        };
+
+        Ok(Video {
+            encoder,
+            palette: palette.to_vec(),
+            width,
+            height,
+            scale,
+            cycles: emulator.cycles(),
+            time: Duration::from_millis(0),
+        })
+    }
+
+    /// Capture the screen if `redraw` is set, called every time the emulator was advanced.
+    pub fn update(&mut self, emulator: &dyn Emulator, redraw: bool) -> Result<(), Box<dyn Error>> {
+        // Rewinding or loading a state goes back in time, which is not part of the recording
+        let cycles = emulator.cycles();
+        let elapsed = cycles.saturating_sub(self.cycles) as f64;
+        self.time += emulator.cycle_dt().mul_f64(elapsed);
+        self.cycles = cycles;
+
+        let pixels = if redraw {
+            Some(indices(emulator, &self.palette, self.scale))
+        } else {
+            None
+        };
+        let (width, height) = (self.width, self.height);
+
+        match &mut self.encoder {
+            Encoder::Gif {
+                encoder,
+                pending,
+                written,
+            } => {
+                let pixels = match pixels {
+                    Some(pixels) if pixels != *pending => pixels,
+                    _ => return Ok(()),
+                };
+
+                // Frames which would be shown too briefly are replaced by the next one
+                let end = centis(self.time);
+                if end - *written >= GIF_MIN_DELAY {
+                    write_gif_frame(encoder, width, height, pending, end - *written)?;
+                    *written = end;
+                }
+                *pending = pixels;
+            }
+            Encoder::Y4m {
+                file,
+                frame: current,
+                written,
+            } => {
+                // Until now, the previous frame was on the screen
+                let end = ticks(self.time);
+                while *written < end {
+                    write_y4m_frame(file, current)?;
+                    *written += 1;
+                }
+                if let Some(pixels) = pixels {
+                    *current = yuv(&pixels, &self.palette);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the last frame and close the video.
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self.encoder {
+            Encoder::Gif {
+                mut encoder,
+                pending,
+                written,
+            } => {
+                let delay = (centis(self.time) - written).max(GIF_MIN_DELAY);
+                write_gif_frame(&mut encoder, self.width, self.height, &pending, delay)?;
+                // The trailer of the GIF is written when the encoder is dropped
+            }
+            Encoder::Y4m {
+                mut file,
+                frame,
+                written,
+            } => {
+                // The last frame lasts until the end, which can be in between two ticks
+                let end = ticks(self.time + Duration::from_nanos(999_999_999) / Y4M_FPS as u32);
+                for _ in written..end.max(1) {
+                    write_y4m_frame(&mut file, &frame)?;
+                }
+                file.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Scaled pixels of the emulator, as indices into the palette
+fn indices(emulator: &dyn Emulator, palette: &[Colour], scale: usize) -> Vec<u8> {
+    let mut pixels = emulator.framebuffer().scaled(scale);
+    for px in pixels.iter_mut() {
+        *px = (*px as usize % palette.len()) as u8;
+    }
+    pixels
+}
+
+// Planar YUV 4:4:4 frame, using the BT.601 colour space
+fn yuv(pixels: &[u8], palette: &[Colour]) -> Vec<u8> {
+    let colours: Vec<[u8; 3]> = palette
+        .iter()
+        .map(|Colour(r, g, b)| {
+            let (r, g, b) = (*r as f32, *g as f32, *b as f32);
+            [
+                16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0,
+                128.0 + (-37.797 * r - 74.203 * g + 112.0 * b) / 255.0,
+                128.0 + (112.0 * r - 93.786 * g - 18.214 * b) / 255.0,
+            ]
+            .map(|val| val.round() as u8)
+        })
+        .collect();
+
+    let colours = &colours;
+    (0..3)
+        .flat_map(|plane| pixels.iter().map(move |px| colours[*px as usize][plane]))
+        .collect()
+}
+
+fn centis(time: Duration) -> u64 {
+    (time.as_millis() / 10) as u64
+}
+
+fn ticks(time: Duration) -> u64 {
+    (time.as_nanos() * Y4M_FPS / 1_000_000_000) as u64
+}
+
+fn write_gif_frame(
+    encoder: &mut gif::Encoder<BufWriter<File>>,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+    delay: u64,
+) -> Result<(), Box<dyn Error>> {
+    let mut frame = gif::Frame::from_indexed_pixels(width as u16, height as u16, pixels, None);
+    frame.delay = delay.min(u16::MAX as u64) as u16;
+    encoder.write_frame(&frame)?;
+    Ok(())
+}
+
+fn write_y4m_frame(file: &mut BufWriter<File>, frame: &[u8]) -> Result<(), Box<dyn Error>> {
+    file.write_all(b"FRAME\n")?;
+    file.write_all(frame)?;
+    Ok(())
+}
@@ -0,0 +1,239 @@
+//! Runs chip8 test roms headlessly and compares their final screen with the expected one.
+//!
+//! See `tests/roms/README.md` for the roms which are used.
+
+use memu::chip8::{asm, Chip8, Platform};
+use memu::headless::{self, Limit};
+use memu::Emulator;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+struct Case {
+    rom: &'static str,
+    golden: &'static str,
+    platform: Platform,
+    frames: u64,
+    /// Value written at `0x1FF`, which picks an entry of the menu of the rom without input
+    menu: Option<u8>,
+}
+
+// Release of the CHIP-8 test suite the golden bitmaps were written with
+const SUITE_URL: &str = "https://github.com/Timendus/chip8-test-suite/raw/v4.1/bin";
+
+// Roms of the CHIP-8 test suite, compared with the golden bitmaps in `tests/golden`
+const CASES: &[Case] = &[
+    Case {
+        rom: "1-chip8-logo",
+        golden: "1-chip8-logo",
+        platform: Platform::Chip8,
+        frames: 60,
+        menu: None,
+    },
+    Case {
+        rom: "2-ibm-logo",
+        golden: "2-ibm-logo",
+        platform: Platform::Chip8,
+        frames: 60,
+        menu: None,
+    },
+    Case {
+        rom: "3-corax+",
+        golden: "3-corax+",
+        platform: Platform::Chip8,
+        frames: 600,
+        menu: None,
+    },
+    Case {
+        rom: "4-flags",
+        golden: "4-flags",
+        platform: Platform::Chip8,
+        frames: 600,
+        menu: None,
+    },
+    Case {
+        rom: "5-quirks",
+        golden: "5-quirks-chip8",
+        platform: Platform::Chip8,
+        frames: 600,
+        menu: Some(1),
+    },
+    Case {
+        rom: "5-quirks",
+        golden: "5-quirks-xochip",
+        platform: Platform::XoChip,
+        frames: 600,
+        menu: Some(3),
+    },
+    // The `EX9E DOWN` test, which shows the keys which are held
+    Case {
+        rom: "6-keypad",
+        golden: "6-keypad",
+        platform: Platform::Chip8,
+        frames: 60,
+        menu: Some(1),
+    },
+];
+
+// Glyphs of the COSMAC VIP font, which chip8 programs draw digits with
+const FONT: [[u8; 5]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0],
+    [0x20, 0x60, 0x20, 0x20, 0x70],
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0],
+    [0x90, 0x90, 0xF0, 0x10, 0x10],
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x10, 0x20, 0x40, 0x40],
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x90, 0x90],
+    [0xE0, 0x90, 0xE0, 0x90, 0xE0],
+    [0xF0, 0x80, 0x80, 0x80, 0xF0],
+    [0xE0, 0x90, 0x90, 0x90, 0xE0],
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x80, 0x80],
+];
+
+fn dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(name)
+}
+
+fn load_rom(name: &str) -> Option<Vec<u8>> {
+    let roms = dir("roms");
+    if let Ok(rom) = fs::read(roms.join(format!("{}.ch8", name))) {
+        return Some(rom);
+    }
+
+    let source = fs::read_to_string(roms.join(format!("{}.asm", name))).ok()?;
+    Some(asm::assemble(&source).unwrap_or_else(|e| panic!("Cannot assemble `{}`: {}", name, e)))
+}
+
+/// Rom of the test suite, downloaded the first time it is needed unless it is in `tests/roms`.
+fn suite_rom(name: &str) -> Option<Vec<u8>> {
+    if let Some(rom) = load_rom(name) {
+        return Some(rom);
+    }
+
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.ch8", name));
+    if !path.exists() {
+        let status = Command::new("curl")
+            .arg("-fsSLo")
+            .arg(&path)
+            .arg(format!("{}/{}.ch8", SUITE_URL, name))
+            .status();
+        if !matches!(status, Ok(status) if status.success()) {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+    }
+    fs::read(&path).ok()
+}
+
+/// Text version of the screen, `.` for unlit pixels and the colour index for lit ones.
+fn bitmap(emulator: &dyn Emulator) -> String {
+    let fb = emulator.framebuffer();
+    let mut res = String::new();
+
+    for y in 0..fb.height {
+        for x in 0..fb.width {
+            match fb.pixel(x, y) {
+                0 => res.push('.'),
+                idx => res.push_str(&idx.to_string()),
+            }
+        }
+        res.push('\n');
+    }
+    res
+}
+
+fn run(platform: Platform, frames: u64, rom: Vec<u8>, menu: Option<u8>) -> String {
+    let mut emulator = Chip8::new(platform, platform.default_quirks());
+    emulator.load_rom(rom);
+    emulator.seed(0);
+    if let Some(choice) = menu {
+        emulator.mem_mut()[0x1FF] = choice;
+    }
+
+    headless::run(&mut emulator, Limit::Frames(frames), None, None, |_, _| ());
+    bitmap(&emulator)
+}
+
+#[test]
+fn flags_rom_shows_the_documented_results() {
+    // Results listed at the top of `flags.asm`, as (value, vF) for each line of both columns
+    let columns = [
+        [(0x00, 1), (0xFF, 0), (0x00, 1), (0xFF, 0), (0x01, 1)],
+        [(0x01, 1), (0x01, 1), (0x01, 1), (0x02, 1), (0x01, 0)],
+    ];
+
+    // Draw the digits where the rom draws them, on a 64x32 screen
+    let mut expected = vec![vec!['.'; 64]; 32];
+    for (column, lines) in columns.iter().enumerate() {
+        for (line, (value, flag)) in lines.iter().enumerate() {
+            let digits = [(0, value >> 4), (5, value & 0xF), (12, *flag)];
+            for (offset, digit) in digits.iter() {
+                for (row, bits) in FONT[*digit as usize].iter().enumerate() {
+                    for col in 0..4 {
+                        if bits & (0x80 >> col) != 0 {
+                            expected[line * 6 + row][column * 32 + offset + col] = '1';
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let expected: String = expected
+        .iter()
+        .map(|row| row.iter().collect::<String>() + "\n")
+        .collect();
+
+    let screen = run(Platform::Chip8, 120, load_rom("flags").unwrap(), None);
+    assert_eq!(screen, expected);
+}
+
+#[test]
+fn test_suite() {
+    let bless = env::var_os("MEMU_BLESS").is_some();
+    let required = env::var_os("MEMU_REQUIRE_ROMS").is_some();
+    let mut failures = Vec::new();
+
+    for case in CASES {
+        let rom = match suite_rom(case.rom) {
+            Some(rom) => rom,
+            None if required => {
+                failures.push(format!("`{}.ch8` cannot be downloaded", case.rom));
+                continue;
+            }
+            None => {
+                eprintln!("Skipping `{}`, its rom cannot be downloaded", case.golden);
+                continue;
+            }
+        };
+
+        let screen = run(case.platform, case.frames, rom, case.menu);
+        let golden = dir("golden").join(format!("{}.txt", case.golden));
+
+        if bless {
+            fs::create_dir_all(dir("golden")).unwrap();
+            fs::write(&golden, &screen).unwrap();
+            eprintln!("Wrote `{}`", golden.display());
+        } else {
+            match fs::read_to_string(&golden) {
+                Ok(expected) if expected == screen => (),
+                Ok(_) => failures.push(format!("`{}` does not match:\n{}", case.golden, screen)),
+                Err(_) => failures.push(format!("`{}` has no golden bitmap", case.golden)),
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\nSee tests/roms/README.md",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,55 @@
+//! Checks that breakpoints stop a chip8 before the instruction they are set on.
+
+use memu::chip8::{Chip8, Platform};
+use memu::debugger::Hit;
+use memu::Emulator;
+
+fn chip8(rom: &[u8], breakpoint: u16) -> Chip8 {
+    let mut emulator = Chip8::new(Platform::Chip8, Platform::Chip8.default_quirks());
+    emulator.load_rom(rom.to_vec());
+    emulator.debugger_mut().set_breakpoint(breakpoint);
+    emulator
+}
+
+#[test]
+fn breakpoint_on_the_entry_point_is_hit() {
+    // LD V0, 1; LD V1, 2
+    let mut emulator = chip8(&[0x60, 0x01, 0x61, 0x02], 0x200);
+
+    emulator.run(10);
+    assert_eq!(emulator.cycles(), 0);
+    assert_eq!(
+        emulator.debugger_mut().take_hit(),
+        Some(Hit::Breakpoint(0x200))
+    );
+
+    // Continuing executes the instruction at the breakpoint
+    emulator.run(1);
+    assert_eq!(emulator.cycles(), 1);
+    assert_eq!(emulator.pc(), 0x202);
+    assert_eq!(emulator.debugger().hit(), None);
+}
+
+#[test]
+fn breakpoint_on_a_jump_to_itself_is_hit_every_time() {
+    // LD V0, 1; JP 0x202
+    let mut emulator = chip8(&[0x60, 0x01, 0x12, 0x02], 0x202);
+
+    for cycles in 1..4 {
+        emulator.run(10);
+        assert_eq!(emulator.cycles(), cycles);
+        assert_eq!(
+            emulator.debugger_mut().take_hit(),
+            Some(Hit::Breakpoint(0x202))
+        );
+    }
+}
+
+#[test]
+fn breakpoint_is_not_hit_while_waiting_for_a_key() {
+    // LD V0, K; LD V1, 2
+    let mut emulator = chip8(&[0xF0, 0x0A, 0x61, 0x02], 0x202);
+
+    emulator.run(10);
+    assert_eq!(emulator.debugger().hit(), None);
+}
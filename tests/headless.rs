@@ -0,0 +1,29 @@
+//! Checks that headless runs emulate exactly the requested amount of time.
+
+use memu::chip8::{Chip8, Platform};
+use memu::headless::{self, Limit};
+use memu::Emulator;
+
+// Jumps onto itself forever
+fn looping_chip8() -> Chip8 {
+    let mut emulator = Chip8::new(Platform::Chip8, Platform::Chip8.default_quirks());
+    emulator.load_rom(vec![0x12, 0x00]);
+    emulator
+}
+
+#[test]
+fn cycles_limit_executes_exactly_n_cycles() {
+    for n in [1, 2, 100, 1234] {
+        let mut emulator = looping_chip8();
+        headless::run(&mut emulator, Limit::Cycles(n), None, None, |_, _| ());
+        assert_eq!(emulator.cycles(), n);
+    }
+}
+
+#[test]
+fn frames_limit_executes_every_cycle_of_the_frames() {
+    let mut emulator = looping_chip8();
+    emulator.set_cycles_per_frame(10);
+    headless::run(&mut emulator, Limit::Frames(60), None, None, |_, _| ());
+    assert_eq!(emulator.cycles(), 600);
+}
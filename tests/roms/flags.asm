@@ -0,0 +1,93 @@
+; Arithmetic flags test for memu.
+;
+; Every line shows the result of an instruction in hexadecimal, followed by the value of vF.
+; The left column subtracts and adds registers, the right column checks that vF holds the flag
+; when it is also the destination, and that 7xkk leaves vF alone.
+;
+;   00 1   01 1     SUB, equal operands      SUB vF, v1
+;   FF 0   01 1     SUB, borrow              ADD vF, v1
+;   00 1   01 1     SUBN, equal operands     SHR
+;   FF 0   02 1     SUBN, borrow             SHL
+;   01 1   01 0     ADD, carry               ADD vX, kk
+
+        CLS
+        LD vA, 0
+        LD vB, 0
+
+        LD v0, 5
+        LD v1, 5
+        SUB v0, v1
+        CALL show
+
+        LD v0, 1
+        LD v1, 2
+        SUB v0, v1
+        CALL show
+
+        LD v0, 5
+        LD v1, 5
+        SUBN v0, v1
+        CALL show
+
+        LD v0, 2
+        LD v1, 1
+        SUBN v0, v1
+        CALL show
+
+        LD v0, 0xFF
+        LD v1, 2
+        ADD v0, v1
+        CALL show
+
+        LD vA, 32
+        LD vB, 0
+
+        LD vF, 5
+        LD v1, 3
+        SUB vF, v1
+        LD v0, vF
+        CALL show
+
+        LD vF, 0xFF
+        LD v1, 2
+        ADD vF, v1
+        LD v0, vF
+        CALL show
+
+        LD v0, 3
+        SHR v0
+        CALL show
+
+        LD v0, 0x81
+        SHL v0
+        CALL show
+
+        LD vF, 0
+        LD v0, 0xFF
+        ADD v0, 2
+        CALL show
+
+end:    JP end
+
+; Draw v0 and vF at (vA, vB), and move to the next line
+show:   LD v2, vF
+        LD v3, v0
+        SHR v3
+        SHR v3
+        SHR v3
+        SHR v3
+        LD v4, 0x0F
+        AND v4, v0
+
+        LD F, v3
+        DRW vA, vB, 5
+        ADD vA, 5
+        LD F, v4
+        DRW vA, vB, 5
+        ADD vA, 7
+        LD F, v2
+        DRW vA, vB, 5
+
+        ADD vA, -12
+        ADD vB, 6
+        RET